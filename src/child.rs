@@ -0,0 +1,558 @@
+//! Tools for running child processes, with support for timeouts and
+//! signals.
+
+use log::debug;
+use std::io::{self, prelude::*};
+use std::process::{self, Child, ExitStatus, Stdio};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command::Command;
+use crate::errors::*;
+
+/// How often should we check to see whether a child process has exited?
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// While waiting for something else, how often should we check for signals?
+pub const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Start running `cmd`. If `process_group` is true, put it in a new process
+/// group, so that we can later signal it along with any children it starts.
+///
+/// A process group which isn't in the foreground can't use the terminal, and
+/// doesn't receive Ctrl-C or Ctrl-Z. So if we're in the foreground, we give
+/// the terminal to the new group, and take it back when the command exits.
+/// While it runs, `wait` and `wait_timeout` handle job control on behalf of
+/// the user's shell.
+pub fn spawn(cmd: &Command, process_group: bool) -> Result<Child> {
+    let terminal = if process_group { terminal() } else { None };
+    let child = spawn_with(cmd, process_group, |command| {
+        if let Some(terminal) = terminal {
+            if is_foreground(terminal) {
+                take_terminal_on_spawn(command, terminal);
+            }
+        }
+    })?;
+    set_job_control_terminal(terminal);
+    Ok(child)
+}
+
+/// Like `spawn`, but capture standard output and standard error using pipes.
+/// We're only watching the command, so it can't read our standard input. In
+/// its own process group, reading from the terminal would stop it with
+/// `SIGTTIN`, so this also keeps it from hanging.
+pub fn spawn_piped(cmd: &Command, process_group: bool) -> Result<Child> {
+    spawn_with(cmd, process_group, |command| {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+    })
+}
+
+/// Start running `cmd`, using `configure` to customize how we run it.
+fn spawn_with<F>(cmd: &Command, process_group: bool, configure: F) -> Result<Child>
+where
+    F: FnOnce(&mut process::Command),
+{
+    let mut command = process::Command::new(&cmd.cmd);
+    command.args(&cmd.args);
+    if process_group {
+        set_new_process_group(&mut command);
+    }
+    configure(&mut command);
+    command.spawn().map_err(|source| Error::CouldNotRun {
+        cmd: cmd.to_owned(),
+        source,
+    })
+}
+
+/// Wait for `child` to exit.
+pub fn wait(child: &mut Child, cmd: &Command) -> Result<ExitStatus> {
+    let status = try_wait(child, true).map_err(|source| Error::CouldNotRun {
+        cmd: cmd.to_owned(),
+        source,
+    })?;
+    Ok(status.expect("blocking wait should return a status"))
+}
+
+/// Wait up to `timeout` for `child` to exit, returning `None` if it's still
+/// running.
+pub fn wait_timeout(
+    child: &mut Child,
+    cmd: &Command,
+    timeout: Duration,
+) -> Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let status = try_wait(child, false).map_err(|source| Error::CouldNotRun {
+            cmd: cmd.to_owned(),
+            source,
+        })?;
+        if status.is_some() {
+            return Ok(status);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Check whether `child` has exited, waiting for it if `block` is true.
+#[cfg(unix)]
+fn try_wait(child: &mut Child, block: bool) -> io::Result<Option<ExitStatus>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let terminal = match JOB_CONTROL_TERMINAL.load(Ordering::SeqCst) {
+        -1 if block => return child.wait().map(Some),
+        -1 => return child.try_wait(),
+        terminal => terminal,
+    };
+
+    // The child might be stopped by job control, which the standard library
+    // doesn't tell us about, so we need to call `waitpid` ourselves.
+    let pid = child.id() as libc::pid_t;
+    let flags = libc::WUNTRACED | if block { 0 } else { libc::WNOHANG };
+    loop {
+        let mut raw_status = 0;
+        // SAFETY: `raw_status` is valid for the duration of the call.
+        match unsafe { libc::waitpid(pid, &mut raw_status, flags) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(None),
+            _ => {
+                let status = ExitStatus::from_raw(raw_status);
+                if let Some(signal) = status.stopped_signal() {
+                    suspend_with_child(pid, terminal, signal);
+                    continue;
+                }
+                // Take back the terminal, if the child still has it.
+                // SAFETY: `getpgrp` has no memory-safety requirements.
+                give_terminal(terminal, pid, unsafe { libc::getpgrp() });
+                JOB_CONTROL_TERMINAL.store(-1, Ordering::SeqCst);
+                return Ok(Some(status));
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn try_wait(child: &mut Child, block: bool) -> io::Result<Option<ExitStatus>> {
+    if block {
+        child.wait().map(Some)
+    } else {
+        child.try_wait()
+    }
+}
+
+/// Ask `child` and the rest of its process group to exit, and kill them if
+/// they're still running after `grace`.
+pub fn terminate(
+    child: &mut Child,
+    cmd: &Command,
+    grace: Duration,
+) -> Result<ExitStatus> {
+    debug!("Sending SIGTERM to {}", cmd);
+    signal_process_group(child, Signal::Terminate);
+    let status = match wait_timeout(child, cmd, grace)? {
+        Some(status) => status,
+        None => {
+            debug!("Sending SIGKILL to {}", cmd);
+            signal_process_group(child, Signal::Kill);
+            wait(child, cmd)?
+        }
+    };
+    // Clean up anything the child left behind in its process group.
+    signal_process_group(child, Signal::Kill);
+    Ok(status)
+}
+
+/// Forward signals received by `alert` to a child process until this is
+/// dropped, so that stopping `alert` also stops the command it's running.
+/// These may be nested, in which case dropping the inner one goes back to
+/// what the outer one was doing.
+pub struct SignalForwarder {
+    /// Where we were forwarding signals before this.
+    #[cfg(unix)]
+    previous_target: libc::pid_t,
+    /// Did we install our signal handlers, or were they already installed?
+    #[cfg(unix)]
+    installed_handlers: bool,
+}
+
+/// The signals that we forward.
+#[cfg(unix)]
+const FORWARDED_SIGNALS: &[libc::c_int] = &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Where to forward signals: a positive PID, a negative process group ID, or
+/// zero if we aren't forwarding signals.
+#[cfg(unix)]
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+/// Are our signal handlers installed?
+#[cfg(unix)]
+static HANDLING_SIGNALS: AtomicBool = AtomicBool::new(false);
+
+/// The last signal we received, or zero.
+#[cfg(unix)]
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Start forwarding signals to `child`. If `process_group` is true, we
+/// forward them to the child's entire process group, which must have been
+/// created by `spawn`.
+#[cfg(unix)]
+pub fn forward_signals(child: &Child, process_group: bool) -> SignalForwarder {
+    let pid = child.id() as libc::pid_t;
+    handle_signals(if process_group { -pid } else { pid })
+}
+
+#[cfg(not(unix))]
+pub fn forward_signals(_child: &Child, _process_group: bool) -> SignalForwarder {
+    SignalForwarder {}
+}
+
+/// Record signals without forwarding them anywhere, so that we can check
+/// `received_signal` instead of exiting immediately.
+#[cfg(unix)]
+pub fn catch_signals() -> SignalForwarder {
+    handle_signals(0)
+}
+
+#[cfg(not(unix))]
+pub fn catch_signals() -> SignalForwarder {
+    SignalForwarder {}
+}
+
+/// Install our signal handlers if necessary, and forward signals to
+/// `target`.
+#[cfg(unix)]
+fn handle_signals(target: libc::pid_t) -> SignalForwarder {
+    let previous_target = FORWARD_TO.swap(target, Ordering::SeqCst);
+    let installed_handlers = !HANDLING_SIGNALS.swap(true, Ordering::SeqCst);
+    if installed_handlers {
+        for &signal in FORWARDED_SIGNALS {
+            set_signal_handler(
+                signal,
+                forward_signal as *const () as libc::sighandler_t,
+            );
+        }
+    }
+    SignalForwarder {
+        previous_target,
+        installed_handlers,
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalForwarder {
+    fn drop(&mut self) {
+        if self.installed_handlers {
+            for &signal in FORWARDED_SIGNALS {
+                set_signal_handler(signal, libc::SIG_DFL);
+            }
+            HANDLING_SIGNALS.store(false, Ordering::SeqCst);
+        }
+        FORWARD_TO.store(self.previous_target, Ordering::SeqCst);
+    }
+}
+
+/// Our signal handler, which may only call async-signal-safe functions.
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+    let target = FORWARD_TO.load(Ordering::SeqCst);
+    // We can't tell whether a SIGINT came from Ctrl-C, which the terminal
+    // may also have sent to the child, or from something like a CI runner,
+    // which only sent it to us. So always forward it, because a duplicate
+    // SIGINT is harmless.
+    if target != 0 {
+        // SAFETY: `kill` is async-signal-safe.
+        unsafe {
+            libc::kill(target, signal);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_signal_handler(signal: libc::c_int, handler: libc::sighandler_t) {
+    // SAFETY: We fully initialize `action`, and our handler is
+    // async-signal-safe.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// The last signal that `alert` received while forwarding signals, if any.
+#[cfg(unix)]
+pub fn received_signal() -> Option<i32> {
+    match RECEIVED_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn received_signal() -> Option<i32> {
+    None
+}
+
+/// The signal which terminated a process, if any.
+#[cfg(unix)]
+pub fn exit_signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// The exit status of a process killed by `signal`, so that we can exit as
+/// if we had been killed by it, too.
+#[cfg(unix)]
+pub fn signal_status(signal: i32) -> Option<ExitStatus> {
+    use std::os::unix::process::ExitStatusExt;
+    Some(ExitStatus::from_raw(signal))
+}
+
+#[cfg(not(unix))]
+pub fn signal_status(_signal: i32) -> Option<ExitStatus> {
+    None
+}
+
+/// Does `signal` normally mean that somebody asked a process to stop, as
+/// opposed to the process crashing?
+#[cfg(unix)]
+pub fn is_interruption(signal: i32) -> bool {
+    FORWARDED_SIGNALS.contains(&signal) || signal == libc::SIGKILL
+}
+
+#[cfg(not(unix))]
+pub fn is_interruption(_signal: i32) -> bool {
+    false
+}
+
+/// Exit the same way as a child process which exited with `status`, so that
+/// whoever ran `alert` sees what they would have seen without it. If the
+/// child was killed by a signal, we kill ourselves with the same signal.
+pub fn exit_like(status: ExitStatus) -> ! {
+    #[cfg(unix)]
+    {
+        if let Some(signal) = exit_signal(status) {
+            debug!("Exiting with {}", signal_name(signal));
+            let _ = io::stdout().flush();
+            // SAFETY: We fully initialize everything we pass to these
+            // functions.
+            unsafe {
+                // Don't dump core just because the child did.
+                let no_core = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+                libc::signal(signal, libc::SIG_DFL);
+                let mut signals: libc::sigset_t = std::mem::zeroed();
+                libc::sigemptyset(&mut signals);
+                libc::sigaddset(&mut signals, signal);
+                libc::sigprocmask(libc::SIG_UNBLOCK, &signals, std::ptr::null_mut());
+                libc::raise(signal);
+            }
+        }
+    }
+    // We're still here, so fall back to what a shell would report.
+    let code = shell_exit_code(status);
+    debug!("Exiting with code {}", code);
+    process::exit(code)
+}
+
+/// The exit code a shell would report for `status`, which is 128 plus the
+/// signal number if the process was killed by a signal.
+fn shell_exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| exit_signal(status).map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/// A human-readable name for `signal`.
+pub fn signal_name(signal: i32) -> String {
+    #[cfg(unix)]
+    {
+        let name = match signal {
+            libc::SIGHUP => Some("SIGHUP"),
+            libc::SIGINT => Some("SIGINT"),
+            libc::SIGQUIT => Some("SIGQUIT"),
+            libc::SIGABRT => Some("SIGABRT"),
+            libc::SIGKILL => Some("SIGKILL"),
+            libc::SIGSEGV => Some("SIGSEGV"),
+            libc::SIGPIPE => Some("SIGPIPE"),
+            libc::SIGTERM => Some("SIGTERM"),
+            _ => None,
+        };
+        if let Some(name) = name {
+            return name.to_owned();
+        }
+    }
+    format!("signal {}", signal)
+}
+
+/// Signals we know how to send.
+#[derive(Clone, Copy, Debug)]
+enum Signal {
+    /// Politely ask a process to exit.
+    Terminate,
+    /// Force a process to exit.
+    Kill,
+}
+
+#[cfg(unix)]
+fn set_new_process_group(command: &mut process::Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: `setpgid` is async-signal-safe, and we don't allocate.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn set_new_process_group(_command: &mut process::Command) {}
+
+/// The terminal used by the child we're waiting for, if it's in its own
+/// process group and we need to do job control for it, or -1.
+#[cfg(unix)]
+static JOB_CONTROL_TERMINAL: AtomicI32 = AtomicI32::new(-1);
+
+/// Our terminal, if we have one.
+#[cfg(unix)]
+fn terminal() -> Option<libc::c_int> {
+    let fds = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO];
+    // SAFETY: `isatty` has no memory-safety requirements.
+    fds.iter()
+        .copied()
+        .find(|&fd| unsafe { libc::isatty(fd) } == 1)
+}
+
+#[cfg(not(unix))]
+fn terminal() -> Option<i32> {
+    None
+}
+
+/// Is our process group in the foreground of `terminal`?
+#[cfg(unix)]
+fn is_foreground(terminal: libc::c_int) -> bool {
+    // SAFETY: These functions have no memory-safety requirements.
+    unsafe { libc::tcgetpgrp(terminal) == libc::getpgrp() }
+}
+
+#[cfg(not(unix))]
+fn is_foreground(_terminal: i32) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn set_job_control_terminal(terminal: Option<libc::c_int>) {
+    JOB_CONTROL_TERMINAL.store(terminal.unwrap_or(-1), Ordering::SeqCst);
+}
+
+#[cfg(not(unix))]
+fn set_job_control_terminal(_terminal: Option<i32>) {}
+
+/// If process group `from` is in the foreground of `terminal`, put process
+/// group `to` there instead. This only calls async-signal-safe functions.
+#[cfg(unix)]
+fn give_terminal(terminal: libc::c_int, from: libc::pid_t, to: libc::pid_t) {
+    // SAFETY: These functions have no memory-safety requirements, and we
+    // restore the previous SIGTTOU handler.
+    unsafe {
+        if libc::tcgetpgrp(terminal) == from {
+            // Processes outside the foreground get SIGTTOU when they try
+            // this.
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(terminal, to);
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+/// Make the child's new process group the foreground of `terminal`. We do
+/// this in the child before it runs, so that it can't try to use the
+/// terminal too early.
+#[cfg(unix)]
+fn take_terminal_on_spawn(command: &mut process::Command, terminal: libc::c_int) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: `give_terminal` is async-signal-safe, and we don't allocate.
+    unsafe {
+        let parent_group = libc::getpgrp();
+        command.pre_exec(move || {
+            // If this fails, the command can still run in the background.
+            give_terminal(terminal, parent_group, libc::getpgrp());
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn take_terminal_on_spawn(_command: &mut process::Command, _terminal: i32) {}
+
+/// Our child was stopped by `signal`, probably because the user pressed
+/// Ctrl-Z. Do the same thing, so that the user's shell sees that we stopped
+/// and takes back the terminal. When we're continued, continue the child.
+#[cfg(unix)]
+fn suspend_with_child(pid: libc::pid_t, terminal: libc::c_int, signal: libc::c_int) {
+    debug!(
+        "Child was stopped by {}, so stopping too",
+        signal_name(signal)
+    );
+    // SAFETY: These functions have no memory-safety requirements.
+    unsafe {
+        let group = libc::getpgrp();
+        give_terminal(terminal, pid, group);
+        // Unlike SIGTSTP, SIGSTOP can't be ignored, even if our process
+        // group has been orphaned. Otherwise we'd keep continuing the child.
+        libc::raise(libc::SIGSTOP);
+        debug!("Continuing child");
+        give_terminal(terminal, group, pid);
+        libc::kill(-pid, libc::SIGCONT);
+    }
+}
+
+/// Send `signal` to the process group led by `child`. We ignore errors,
+/// because the processes may have already exited.
+#[cfg(unix)]
+fn signal_process_group(child: &mut Child, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // SAFETY: `kill` has no memory-safety requirements.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+/// We don't have process groups, so just kill `child`.
+#[cfg(not(unix))]
+fn signal_process_group(child: &mut Child, _signal: Signal) {
+    let _ = child.kill();
+}
@@ -1,12 +1,16 @@
 //! Our `alert run` subcommand.
 
 use clap::AppSettings;
-use std::process;
+use log::debug;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
+use crate::child;
 use crate::command::Command;
+use crate::config::Config;
 use crate::errors::*;
-use crate::notify::{Notification, Notifier, Outcome};
+use crate::notify::{Notifier, NotifyOpt, Outcome};
+use crate::util::parse_duration;
 
 /// Options for `run`.
 #[derive(Debug, StructOpt)]
@@ -16,23 +20,114 @@ use crate::notify::{Notification, Notifier, Outcome};
     setting(AppSettings::TrailingVarArg)
 )]
 pub struct Opt {
+    /// Stop the command if it runs for too long, and report a timeout.
+    #[structopt(
+        short = "t",
+        long = "timeout",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    timeout: Option<Duration>,
+
+    /// After a timeout, wait this long for the command to exit before killing
+    /// it.
+    #[structopt(
+        long = "kill-after",
+        value_name = "DURATION",
+        default_value = "10",
+        parse(try_from_str = parse_duration)
+    )]
+    kill_after: Duration,
+
+    /// Only notify if the command runs for at least this long (or fails).
+    #[structopt(
+        long = "min-duration",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    min_duration: Option<Duration>,
+
+    /// Don't notify about failures shorter than `--min-duration`, either.
+    #[structopt(long = "ignore-short-failures")]
+    ignore_short_failures: bool,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
+
     /// The command to run, with any arguments.
     cmd: Vec<String>,
 }
 
-pub fn run(opt: &Opt, notifier: &dyn Notifier) -> Result<()> {
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
     let cmd = Command::from_slice(&opt.cmd)?;
-    let status = process::Command::new(&cmd.cmd)
-        .args(&cmd.args)
-        .status()
-        .map_err(|source| Error::CouldNotRun {
-            cmd: (&cmd).to_owned(),
-            source,
-        })?;
-    let notification =
-        Notification::new(Outcome::from_bool(status.success())).command(cmd);
-    notifier.send(&notification)?;
-    if status.success() {
+    let min_duration = match opt.min_duration {
+        Some(min_duration) => Some(min_duration),
+        None => config.run().min_duration(),
+    };
+    let notify_short_failures =
+        !opt.ignore_short_failures && config.run().notify_short_failures();
+    let start = Instant::now();
+
+    // Run the command in its own process group, so that we can stop any
+    // children it starts, too.
+    let mut child = match child::spawn(&cmd, true) {
+        Ok(child) => child,
+        Err(err) => {
+            let notification = opt
+                .notify
+                .notification(config, Outcome::Error)
+                .with_command(cmd)
+                .with_error(err.to_string());
+            notifier.send(&notification)?;
+            return Err(err);
+        }
+    };
+    let forwarder = child::forward_signals(&child, true);
+    let (mut outcome, status) = match opt.timeout {
+        None => {
+            let status = child::wait(&mut child, &cmd)?;
+            (Outcome::from_bool(status.success()), status)
+        }
+        Some(timeout) => match child::wait_timeout(&mut child, &cmd, timeout)? {
+            Some(status) => (Outcome::from_bool(status.success()), status),
+            None => {
+                let status = child::terminate(&mut child, &cmd, opt.kill_after)?;
+                (Outcome::Timeout, status)
+            }
+        },
+    };
+    drop(forwarder);
+
+    // Were we interrupted by the user or by a signal?
+    let mut signal = None;
+    if outcome == Outcome::Failure {
+        if let Some(received) = child::received_signal() {
+            outcome = Outcome::Interrupted;
+            signal = Some(received);
+        } else if let Some(exit_signal) = child::exit_signal(status) {
+            if child::is_interruption(exit_signal) {
+                outcome = Outcome::Interrupted;
+            }
+            signal = Some(exit_signal);
+        }
+    }
+
+    // Don't bother the user about commands that finished quickly.
+    let duration = start.elapsed();
+    let too_short = min_duration.is_some_and(|min| duration < min);
+    if too_short && (outcome == Outcome::Success || !notify_short_failures) {
+        debug!("Command finished in {:?}, not notifying", duration);
+    } else {
+        let notification = opt
+            .notify
+            .notification(config, outcome)
+            .with_command(cmd)
+            .exit_code(status.code())
+            .signal(signal)
+            .duration(duration);
+        notifier.send(&notification)?;
+    }
+    if outcome == Outcome::Success {
         Ok(())
     } else {
         Err(Error::CommandFailedOrTimedOut {
@@ -0,0 +1,58 @@
+//! Our `alert send` subcommand.
+
+use std::io::{self, prelude::*};
+use structopt::StructOpt;
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::notify::{Notifier, NotifyOpt, Outcome};
+
+/// Options for `send`.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Sends a notification without running a command")]
+pub struct Opt {
+    /// The outcome to report, which controls the default title, sound and
+    /// priority.
+    #[structopt(
+        short = "o",
+        long = "outcome",
+        value_name = "OUTCOME",
+        default_value = "success",
+        possible_values = &[
+            "success", "failure", "timeout", "interrupted", "error", "changed", "finished"
+        ]
+    )]
+    outcome: Outcome,
+
+    /// The title of the notification.
+    #[structopt(long = "title", value_name = "TITLE")]
+    title: Option<String>,
+
+    /// The body of the notification. Use "-" to read it from standard input.
+    #[structopt(short = "m", long = "message", value_name = "MESSAGE")]
+    message: Option<String>,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
+}
+
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
+    let mut notification = opt.notify.notification(config, opt.outcome);
+    if let Some(title) = &opt.title {
+        notification = notification.with_title(title.to_owned());
+    }
+    match opt.message.as_deref() {
+        Some("-") => {
+            let mut message = String::new();
+            io::stdin()
+                .read_to_string(&mut message)
+                .map_err(|source| Error::CouldNotReadStdin { source })?;
+            notification = notification.with_message(message.trim_end().to_owned());
+        }
+        Some(message) => {
+            notification = notification.with_message(message.to_owned());
+        }
+        None => {}
+    }
+    notifier.send(&notification)
+}
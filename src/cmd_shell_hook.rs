@@ -0,0 +1,81 @@
+//! Our `alert shell-hook` subcommand, which is called by the hooks printed by
+//! `alert shell-init`.
+
+use log::debug;
+use std::env;
+use std::process;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use crate::child;
+use crate::command::Command;
+use crate::config::Config;
+use crate::errors::*;
+use crate::notify::{Notifier, NotifyOpt, Outcome};
+use crate::util::parse_duration;
+
+/// Options for `shell-hook`.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Reports a command run by an interactive shell")]
+pub struct Opt {
+    /// The exit code of the command.
+    #[structopt(long = "exit-code", value_name = "CODE")]
+    exit_code: i32,
+
+    /// How long the command ran.
+    #[structopt(
+        long = "duration",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    duration: Duration,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
+
+    /// The command line, as typed into the shell.
+    command: String,
+}
+
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
+    // If the user is looking at the terminal, they already know.
+    if terminal_is_focused() {
+        debug!("Terminal is focused, not notifying");
+        return Ok(());
+    }
+
+    // Shells report commands killed by signal N as exiting with 128 + N.
+    let signal = Some(opt.exit_code - 128).filter(|&s| s > 0);
+    let outcome = match signal {
+        Some(signal) if child::is_interruption(signal) => Outcome::Interrupted,
+        _ => Outcome::from_bool(opt.exit_code == 0),
+    };
+
+    let notification = opt
+        .notify
+        .notification(config, outcome)
+        .with_command(Command::from_shell_line(&opt.command)?)
+        .exit_code(Some(opt.exit_code))
+        .signal(signal.filter(|_| outcome == Outcome::Interrupted))
+        .duration(opt.duration);
+    notifier.send(&notification)
+}
+
+/// Is the terminal which ran the command the active window? We can only tell
+/// on X11, in terminals which set `WINDOWID`, and only if `xdotool` is
+/// installed. Everywhere else, including Wayland, macOS and SSH sessions, we
+/// assume the terminal isn't focused and always notify.
+fn terminal_is_focused() -> bool {
+    let window_id = match env::var("WINDOWID") {
+        Ok(window_id) => window_id,
+        Err(_) => return false,
+    };
+    let output = match process::Command::new("xdotool")
+        .arg("getactivewindow")
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return false,
+    };
+    String::from_utf8_lossy(&output.stdout).trim() == window_id.trim()
+}
@@ -0,0 +1,159 @@
+//! Our `alert shell-init` subcommand.
+
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::util::parse_duration;
+
+/// How long an interactive command must run before we notify, unless the user
+/// tells us otherwise.
+const DEFAULT_MIN_DURATION: Duration = Duration::from_secs(30);
+
+/// Hooks for `bash`. If `bash-preexec` has been loaded, we add ourselves to
+/// its `preexec_functions` and `precmd_functions`. Otherwise we need bash 4.4
+/// or later, because we use `PS0` to notice when the user has entered a
+/// command. The arithmetic expansion in `PS0` sets `__alert_ready` without
+/// running a subshell, and then our `DEBUG` trap records `$BASH_COMMAND` and
+/// the start time, before running any `DEBUG` trap which was already set. We
+/// can't rely on `history` here, because `HISTCONTROL` and `HISTIGNORE` may
+/// leave commands out of it. For a pipeline or a list of commands, we only see
+/// the first one. Our `PROMPT_COMMAND` has to run first to see the command's
+/// exit status, so we put it at the start, whether `PROMPT_COMMAND` is a
+/// string or an array.
+const BASH_HOOKS: &str = r#"__alert_record() {
+    __alert_cmd=$1
+    __alert_start=$SECONDS
+}
+__alert_preexec() {
+    local status=$?
+    if [ -n "$__alert_ready" ]; then
+        __alert_ready=
+        __alert_record "$BASH_COMMAND"
+    fi
+    return $status
+}
+__alert_precmd() {
+    local exit_status=$?
+    __alert_ready=
+    if [ -n "$__alert_start" ]; then
+        local elapsed=$((SECONDS - __alert_start))
+        if [ "$elapsed" -ge __ALERT_MIN_DURATION__ ]; then
+            (command alert shell-hook --exit-code "$exit_status" --duration "$elapsed" -- "$__alert_cmd" >/dev/null 2>&1 &)
+        fi
+    fi
+    __alert_start=
+}
+if [ -z "$__alert_installed" ]; then
+    __alert_installed=1
+    if [ -n "${bash_preexec_imported:-}${__bp_imported:-}" ]; then
+        preexec_functions+=(__alert_record)
+        precmd_functions+=(__alert_precmd)
+    else
+        PS0="${PS0}"'${__alert_ready:0:$((__alert_ready=1,0))}'
+        __alert_parse_trap() { __alert_previous_trap=$2; }
+        __alert_previous_trap=$(trap -p DEBUG)
+        eval "__alert_parse_trap ${__alert_previous_trap#trap}"
+        trap "__alert_preexec${__alert_previous_trap:+; $__alert_previous_trap}" DEBUG
+        unset -f __alert_parse_trap
+        unset __alert_previous_trap
+        if [[ ${PROMPT_COMMAND@a} == *a* ]]; then
+            PROMPT_COMMAND=(__alert_precmd "${PROMPT_COMMAND[@]}")
+        else
+            PROMPT_COMMAND="__alert_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+        fi
+    fi
+fi
+"#;
+
+/// Hooks for `zsh`.
+const ZSH_HOOKS: &str = r#"autoload -Uz add-zsh-hook
+__alert_preexec() {
+    __alert_cmd=$1
+    __alert_start=$SECONDS
+}
+__alert_precmd() {
+    local exit_status=$?
+    if [[ -n $__alert_start ]]; then
+        local elapsed=$((SECONDS - __alert_start))
+        if (( elapsed >= __ALERT_MIN_DURATION__ )); then
+            command alert shell-hook --exit-code "$exit_status" --duration "$elapsed" -- "$__alert_cmd" >/dev/null 2>&1 &!
+        fi
+    fi
+    __alert_start=
+}
+add-zsh-hook preexec __alert_preexec
+add-zsh-hook precmd __alert_precmd
+"#;
+
+/// Hooks for `fish`, which times commands for us.
+const FISH_HOOKS: &str = r#"function __alert_postexec --on-event fish_postexec
+    set -l exit_status $status
+    set -l elapsed (math --scale=0 $CMD_DURATION / 1000)
+    if test -n "$argv[1]"; and test $elapsed -ge __ALERT_MIN_DURATION__
+        command alert shell-hook --exit-code $exit_status --duration $elapsed -- $argv[1] >/dev/null 2>&1 &
+        disown 2>/dev/null
+    end
+end
+"#;
+
+/// Options for `shell-init`.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Prints shell hooks which notify about slow commands")]
+pub struct Opt {
+    /// Only notify about commands which run for at least this long. Defaults
+    /// to `min_duration` in the `[run]` section of the config file, or 30s.
+    #[structopt(
+        long = "min-duration",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    min_duration: Option<Duration>,
+
+    /// The shell to print hooks for.
+    #[structopt(possible_values = &["bash", "zsh", "fish"])]
+    shell: Shell,
+}
+
+/// Shells which we support.
+#[derive(Clone, Copy, Debug)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Shell> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(Error::UnknownShell { name: s.to_owned() }),
+        }
+    }
+}
+
+pub fn run(opt: &Opt, config: &Config) -> Result<()> {
+    let min_duration = match opt.min_duration {
+        Some(min_duration) => min_duration,
+        None => config.run().min_duration().unwrap_or(DEFAULT_MIN_DURATION),
+    };
+    let hooks = match opt.shell {
+        Shell::Bash => BASH_HOOKS,
+        Shell::Zsh => ZSH_HOOKS,
+        Shell::Fish => FISH_HOOKS,
+    };
+    print!(
+        "{}",
+        hooks.replace(
+            "__ALERT_MIN_DURATION__",
+            &min_duration.as_secs().to_string()
+        )
+    );
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Our `alert wait` subcommand.
+
+use log::debug;
+use regex::Regex;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+use crate::child;
+use crate::config::Config;
+use crate::errors::*;
+use crate::notify::{Notifier, NotifyOpt, Outcome};
+use crate::procfs;
+use crate::util::parse_duration;
+
+/// Options for `wait`.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Waits for a running process and notifies when it finishes")]
+pub struct Opt {
+    /// The ID of the process to wait for.
+    #[structopt(
+        short = "p",
+        long = "pid",
+        value_name = "PID",
+        required_unless = "name",
+        conflicts_with = "name"
+    )]
+    pid: Option<u32>,
+
+    /// Wait for the only process whose command line matches this regex.
+    #[structopt(long = "name", value_name = "REGEX")]
+    name: Option<Regex>,
+
+    /// How often to check whether the process has exited, if we can't trace
+    /// it.
+    #[structopt(
+        short = "n",
+        long = "interval",
+        value_name = "DURATION",
+        default_value = "1",
+        parse(try_from_str = parse_duration)
+    )]
+    interval: Duration,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
+}
+
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
+    let pid = match (&opt.pid, &opt.name) {
+        (Some(pid), _) => *pid,
+        (None, Some(name)) => procfs::find(name)?,
+        (None, None) => unreachable!("clap should require --pid or --name"),
+    };
+
+    // Look up everything we need before the process goes away.
+    let cmd = procfs::command(pid)?;
+    let elapsed_before_wait = procfs::elapsed(pid).unwrap_or_default();
+    debug!("Waiting for process {}: {}", pid, cmd);
+    let start = Instant::now();
+    let status = procfs::wait(pid, opt.interval)?;
+    let duration = elapsed_before_wait + start.elapsed();
+
+    let mut notification = match &status {
+        Some(status) => {
+            let signal = child::exit_signal(*status);
+            let outcome = match signal {
+                Some(signal) if child::is_interruption(signal) => Outcome::Interrupted,
+                _ => Outcome::from_bool(status.success()),
+            };
+            opt.notify
+                .notification(config, outcome)
+                .exit_code(status.code())
+                .signal(signal)
+        }
+        // We don't know whether the process succeeded, so don't say.
+        None => opt.notify.notification(config, Outcome::Finished),
+    };
+    notification = notification.with_command(cmd).duration(duration);
+    notifier.send(&notification)?;
+    match status {
+        Some(status) if !status.success() => Err(Error::CommandFailedOrTimedOut {
+            status: Some(status),
+        }),
+        _ => Ok(()),
+    }
+}
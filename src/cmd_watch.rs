@@ -1,17 +1,37 @@
 //! Our `alert run` subcommand.
 
 use clap::AppSettings;
+use log::{debug, warn};
 use regex::Regex;
-use std::io;
-use std::io::prelude::*;
 use std::process;
 use std::thread;
 use std::time;
 use structopt::StructOpt;
 
+use crate::child;
 use crate::command::Command;
+use crate::config::Config;
+use crate::diff;
 use crate::errors::*;
-use crate::notify::{Notification, Notifier, Outcome};
+use crate::matcher::{ExitOpt, JsonOpt, MatchOpt, Matcher, NumberOpt};
+use crate::notify::{Notification, Notifier, NotifyOpt, Outcome, Progress};
+use crate::output::{self, Line, Tail};
+use crate::schedule::{Backoff, Schedule};
+use crate::screen::{self, Screen};
+use crate::util::parse_duration;
+
+/// How many lines of output should we make available to templates?
+const OUTPUT_TAIL_LINES: usize = 10;
+
+/// How many changed lines should we include in `--on-change` notifications?
+const DIFF_LINES: usize = 10;
+
+/// How often should we tell the notifier that we're still waiting?
+const PROGRESS_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
+/// When we stop a command in `--follow` mode, how long should we wait for it
+/// to exit before killing it?
+const STOP_GRACE_PERIOD: time::Duration = time::Duration::from_secs(10);
 
 /// Options for `watch`.
 #[derive(Debug, StructOpt)]
@@ -21,121 +41,449 @@ use crate::notify::{Notification, Notifier, Outcome};
     setting(AppSettings::TrailingVarArg)
 )]
 pub struct Opt {
-    /// Report success when matching text appears.
-    #[structopt(short = "s", long = "success", value_name = "SUCCESS_REGEX")]
-    success: Option<Regex>,
+    #[structopt(flatten)]
+    matcher: MatchOpt,
+
+    #[structopt(flatten)]
+    exit: ExitOpt,
 
-    /// Report failure when matching text appears.
-    #[structopt(short = "f", long = "failure", value_name = "FAILURE_REGEX")]
-    failure: Option<Regex>,
+    #[structopt(flatten)]
+    json: JsonOpt,
 
-    /// Give up if nothing happens after a wait.
-    #[structopt(short = "t", long = "timeout", value_name = "SECONDS")]
-    timeout: Option<u64>,
+    #[structopt(flatten)]
+    number: NumberOpt,
 
-    /// Time to wait between runs.
+    /// Time between the start of one run and the next, such as "500ms", "30"
+    /// or "2m". Bare numbers are seconds.
     #[structopt(
         short = "n",
         long = "interval",
-        value_name = "SECONDS",
-        default_value = "2"
+        value_name = "DURATION",
+        default_value = "2",
+        parse(try_from_str = parse_duration)
+    )]
+    interval: time::Duration,
+
+    /// How to change the interval while nothing is happening. With
+    /// "exponential", we double it after each run, and go back to the
+    /// original interval whenever the output changes, ignoring any text
+    /// matching `--ignore`.
+    #[structopt(
+        long = "backoff",
+        value_name = "BACKOFF",
+        default_value = "constant",
+        possible_values = Backoff::variants()
+    )]
+    backoff: Backoff,
+
+    /// Never back off to an interval longer than this. Defaults to 1 hour.
+    #[structopt(
+        long = "max-interval",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
     )]
-    interval: u64,
+    max_interval: Option<time::Duration>,
+
+    /// Vary each interval randomly by up to 10%, so that lots of watchers
+    /// don't all run at the same moment.
+    #[structopt(long = "jitter")]
+    jitter: bool,
+
+    /// Give up after running the command this many times, and report a
+    /// timeout.
+    #[structopt(long = "max-runs", value_name = "N", conflicts_with = "follow")]
+    max_runs: Option<u64>,
+
+    /// Notify whenever the output differs from the previous run.
+    #[structopt(long = "on-change", conflicts_with = "follow")]
+    on_change: bool,
+
+    /// Ignore any text matching this regex, such as timestamps, when
+    /// deciding whether the output changed. This applies to `--on-change`
+    /// and to resetting `--backoff`. May be given more than once.
+    #[structopt(long = "ignore", value_name = "REGEX", number_of_values = 1)]
+    ignore: Vec<Regex>,
+
+    /// In `--on-change` mode, keep watching after we notify about a change.
+    #[structopt(long = "keep-watching", requires = "on-change")]
+    keep_watching: bool,
+
+    /// Clear the screen and redraw the latest output after each run, like
+    /// `watch(1)`, highlighting matches and changes. Ignored unless standard
+    /// output is a terminal.
+    #[structopt(long = "screen", conflicts_with = "follow")]
+    screen: bool,
+
+    /// Run the command once, and check each line of output as it appears.
+    /// Use this for commands like `tail -f` which never exit.
+    #[structopt(long = "follow")]
+    follow: bool,
+
+    /// In `--follow` mode, leave the command running after a match, instead
+    /// of stopping it. We exit right away and stop reading its output, so
+    /// the command should send its output somewhere else if it keeps
+    /// writing.
+    #[structopt(long = "leave-running", requires = "follow")]
+    leave_running: bool,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
 
     /// The command to run, with any arguments.
     cmd: Vec<String>,
 }
 
-pub fn run(opt: &Opt, notifier: &dyn Notifier) -> Result<()> {
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
     let cmd = Command::from_slice(&opt.cmd)?;
 
     let start = time::SystemTime::now();
-    let end = opt
-        .timeout
-        .map(|t_o| start + time::Duration::from_secs(t_o));
+    let matcher = opt
+        .matcher
+        .matcher(config)?
+        .with_exit_conditions(opt.exit.conditions())
+        .with_json_conditions(opt.json.conditions())
+        .with_extractor(opt.number.extractor());
+    let end = matcher.deadline(start);
 
+    // Build a notification for the specified outcome.
+    let notification = |outcome, output_tail: &Option<String>| -> Notification {
+        let mut notification = opt
+            .notify
+            .notification(config, outcome)
+            .with_command(cmd.clone())
+            .duration(start.elapsed().unwrap_or_default());
+        if let Some(output_tail) = output_tail {
+            notification = notification.output_tail(output_tail.to_owned());
+        }
+        notification
+    };
+
+    if opt.follow {
+        return follow(opt, &matcher, &cmd, end, &notification, notifier);
+    }
+
+    let mut schedule =
+        Schedule::new(opt.interval, opt.backoff, opt.max_interval, opt.jitter);
+    let mut screen = if opt.screen && !screen::stdout_is_terminal() {
+        warn!("Standard output isn't a terminal, so ignoring --screen");
+        None
+    } else if opt.screen {
+        Some(Screen::new(&cmd, &matcher, schedule.interval(), start)?)
+    } else {
+        None
+    };
+
+    let mut output_tail = None;
+    let mut previous_output: Option<Vec<String>> = None;
+    let mut crossing = opt.number.crossing();
+    let mut runs = 0;
+    let mut last_progress = start;
+
+    // Notice Ctrl-C and friends, so that we can tell the user why we stopped.
+    let _signals = child::catch_signals();
     loop {
         // Run our command once, and figure out what to do.
-        match run_once(&cmd) {
-            Err(err) => {
-                eprintln!("{}", err);
+        let run_start = time::Instant::now();
+        runs += 1;
+        let result = run_once(&cmd, screen.is_none());
+        if let Some(signal) = child::received_signal() {
+            let notification = notification(Outcome::Interrupted, &output_tail);
+            return report_interruption(signal, notification, &mut screen, notifier);
+        }
+        match result {
+            // If we can't run the command the first time, it's probably
+            // missing, so give up.
+            Err(err) if runs == 1 => {
+                finish_screen(&mut screen)?;
+                let notification =
+                    notification(Outcome::Error, &None).with_error(err.to_string());
+                notifier.send(&notification)?;
+                return Err(err);
             }
+            Err(err) => match &mut screen {
+                Some(screen) => screen.update_with_error(&err),
+                None => eprintln!("{}", err),
+            },
             Ok(output) => {
-                // Print out the command's output. We end up undoing any
-                // interleaving in the original output, but it would take a
-                // fair bit more code to avoid this.
-                io::stdout().write(&output.stdout).map_err(|source| {
-                    Error::CouldNotWriteToStdio {
-                        dest: "stdout",
-                        source,
-                    }
-                })?;
-                io::stderr().write(&output.stderr).map_err(|source| {
-                    Error::CouldNotWriteToStdio {
-                        dest: "stderr",
-                        source,
-                    }
-                })?;
-
-                // Convert stdout and stderr to a vector of lines so we
-                // can easily search them both.  (There are other reasonable
-                // ways to do this.)
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                let mut all_lines: Vec<&str> = vec![];
-                all_lines.extend(stdout.lines());
-                all_lines.extend(stderr.lines());
-
-                // Check for failure first so it takes priority.
-                for line in &all_lines {
-                    if let Some(re) = &opt.failure {
-                        if re.is_match(line) {
-                            let notification = Notification::new(Outcome::Failure)
-                                .command(cmd.clone());
-                            notifier.send(&notification)?;
-                            return Err(Error::CommandFailedOrTimedOut {
-                                status: None,
-                            });
-                        }
+                let mut tail = Tail::new(OUTPUT_TAIL_LINES);
+                for line in &output.lines {
+                    tail.push(&line.text);
+                }
+                output_tail = Some(tail.to_text());
+                if let Some(screen) = &mut screen {
+                    screen.update(&output.lines);
+                }
+
+                if let Some(m) = matcher.check_run(&output.lines, output.status) {
+                    finish_screen(&mut screen)?;
+                    let notification = m.annotate(
+                        notification(m.outcome, &output_tail)
+                            .exit_code(output.status.code()),
+                    );
+                    notifier.send(&notification)?;
+                    return if m.outcome == Outcome::Success {
+                        Ok(())
+                    } else {
+                        Err(Error::CommandFailedOrTimedOut { status: None })
+                    };
+                }
+
+                let number = matcher.extract_number(&output.lines);
+                if let (Some(crossing), Some(number)) = (&mut crossing, number) {
+                    if let Some(change) = crossing.update(number) {
+                        let notification =
+                            notification(Outcome::Changed, &output_tail)
+                                .exit_code(output.status.code())
+                                .field("value", number.to_string())
+                                .crossing(change);
+                        notifier.send(&notification)?;
                     }
                 }
 
-                // Check for success.
-                for line in &all_lines {
-                    if let Some(re) = &opt.success {
-                        if re.is_match(line) {
-                            let notification = Notification::new(Outcome::Success)
-                                .command(cmd.clone());
+                // When the output changes, stop backing off, and tell the
+                // user if they asked us to.
+                let current = normalize(&output.lines, &opt.ignore);
+                if let Some(previous) = &previous_output {
+                    if *previous != current {
+                        schedule.reset();
+                        if opt.on_change {
+                            let diff = diff::diff(previous, &current, DIFF_LINES);
+                            let notification =
+                                notification(Outcome::Changed, &output_tail)
+                                    .exit_code(output.status.code())
+                                    .diff(diff);
+                            if !opt.keep_watching {
+                                finish_screen(&mut screen)?;
+                            }
                             notifier.send(&notification)?;
-                            return Ok(());
+                            if !opt.keep_watching {
+                                return Ok(());
+                            }
                         }
                     }
                 }
+                previous_output = Some(current);
             }
         }
 
         // Check our timeout.
         if let Some(end) = end {
             if time::SystemTime::now() >= end {
-                let notification = Notification::new(Outcome::Timeout).command(cmd);
-                notifier.send(&notification)?;
+                finish_screen(&mut screen)?;
+                notifier.send(&notification(Outcome::Timeout, &output_tail))?;
                 return Err(Error::CommandFailedOrTimedOut { status: None });
             }
         }
 
-        // Wait until it's time to run again.
-        thread::sleep(time::Duration::from_secs(opt.interval));
+        // Check how many times we've run.
+        if opt.max_runs.is_some_and(|max_runs| runs >= max_runs) {
+            finish_screen(&mut screen)?;
+            let notification = notification(Outcome::Timeout, &output_tail).runs(runs);
+            notifier.send(&notification)?;
+            return Err(Error::CommandFailedOrTimedOut { status: None });
+        }
+
+        // Let the user know we're still waiting, but not too often.
+        if last_progress.elapsed().unwrap_or_default() >= PROGRESS_INTERVAL {
+            last_progress = time::SystemTime::now();
+            let progress = Progress {
+                command: cmd.clone(),
+                runs,
+                elapsed: start.elapsed().unwrap_or_default(),
+            };
+            // Progress updates are just a courtesy, so don't give up on a
+            // long watch because one of them failed.
+            if let Err(err) = notifier.progress(&progress) {
+                warn!("Could not show progress: {}", err);
+            }
+        }
+
+        // Wait until it's time to run again, counting from the start of
+        // this run.
+        if let Some(screen) = &mut screen {
+            screen.set_interval(schedule.interval());
+        }
+        let wait = schedule.next_wait().saturating_sub(run_start.elapsed());
+        match &screen {
+            Some(screen) => count_down(screen, wait)?,
+            None => sleep_unless_interrupted(wait),
+        }
+        if let Some(signal) = child::received_signal() {
+            let notification = notification(Outcome::Interrupted, &output_tail);
+            return report_interruption(signal, notification, &mut screen, notifier);
+        }
+    }
+}
+
+/// Tell the user that `signal` stopped us, and exit the way it would have.
+fn report_interruption(
+    signal: i32,
+    notification: Notification,
+    screen: &mut Option<Screen>,
+    notifier: &dyn Notifier,
+) -> Result<()> {
+    finish_screen(screen)?;
+    notifier.send(&notification.signal(Some(signal)))?;
+    Err(Error::CommandFailedOrTimedOut {
+        status: child::signal_status(signal),
+    })
+}
+
+/// If we're showing `screen`, switch back to the normal screen and show our
+/// final output there, before we send any notifications.
+fn finish_screen(screen: &mut Option<Screen>) -> Result<()> {
+    match screen.take() {
+        Some(screen) => screen.finish(),
+        None => Ok(()),
+    }
+}
+
+/// Wait for `interval`, showing how long is left on `screen`.
+fn count_down(screen: &Screen, interval: time::Duration) -> Result<()> {
+    let next_run = time::Instant::now() + interval;
+    loop {
+        let remaining = next_run.saturating_duration_since(time::Instant::now());
+        if remaining == time::Duration::from_secs(0)
+            || child::received_signal().is_some()
+        {
+            return Ok(());
+        }
+        screen.draw(Some(remaining))?;
+        sleep_unless_interrupted(remaining.min(time::Duration::from_secs(1)));
+    }
+}
+
+/// Sleep for `duration`, but wake up early if we receive a signal.
+fn sleep_unless_interrupted(duration: time::Duration) {
+    let end = time::Instant::now() + duration;
+    while child::received_signal().is_none() {
+        let remaining = end.saturating_duration_since(time::Instant::now());
+        if remaining == time::Duration::from_secs(0) {
+            return;
+        }
+        thread::sleep(remaining.min(child::SIGNAL_CHECK_INTERVAL));
     }
 }
 
-/// Run a command a single time, and return its output.
-pub fn run_once(cmd: &Command) -> Result<process::Output> {
-    let output = process::Command::new(&cmd.cmd)
-        .args(&cmd.args)
-        .output()
-        .map_err(|source| Error::CouldNotRun {
-            cmd: cmd.to_owned(),
-            source,
-        })?;
-    Ok(output)
+/// Run the command once, checking each line of output as soon as it appears.
+fn follow(
+    opt: &Opt,
+    matcher: &Matcher,
+    cmd: &Command,
+    end: Option<time::SystemTime>,
+    notification: &dyn Fn(Outcome, &Option<String>) -> Notification,
+    notifier: &dyn Notifier,
+) -> Result<()> {
+    // Run the command in its own process group, so that we can stop it and
+    // anything it starts.
+    let mut child = match child::spawn_piped(cmd, true) {
+        Ok(child) => child,
+        Err(err) => {
+            let notification =
+                notification(Outcome::Error, &None).with_error(err.to_string());
+            notifier.send(&notification)?;
+            return Err(err);
+        }
+    };
+    let forwarder = child::forward_signals(&child, true);
+    let lines = output::read_lines(&mut child);
+    let mut tail = Tail::new(OUTPUT_TAIL_LINES);
+
+    // Wait for a match, a timeout, or for the command to exit.
+    let matched = matcher.wait_for_match(&lines, end, &mut tail)?;
+
+    let outcome = match matched {
+        Some(m) => {
+            let notification =
+                m.annotate(notification(m.outcome, &Some(tail.to_text())));
+            notifier.send(&notification)?;
+            if opt.leave_running {
+                // Commands like `tail -f` never exit, so don't wait for it.
+                // Once we exit, nobody is reading its output, so it will get
+                // `SIGPIPE` if it writes anything else.
+                debug!("Leaving {} running", cmd);
+            } else {
+                child::terminate(&mut child, cmd, STOP_GRACE_PERIOD)?;
+            }
+            m.outcome
+        }
+        None => {
+            // The command exited before we saw anything interesting.
+            let status = child::wait(&mut child, cmd)?;
+            if let Some(signal) = child::received_signal() {
+                drop(forwarder);
+                let notification =
+                    notification(Outcome::Interrupted, &Some(tail.to_text()))
+                        .exit_code(status.code());
+                return report_interruption(signal, notification, &mut None, notifier);
+            }
+            let exit_match = matcher.check_exit(status);
+            let outcome = if let Some(m) = &exit_match {
+                m.outcome
+            } else {
+                // Even a successful exit isn't what we were waiting for,
+                // unless the user asked for it with `--until-success`.
+                Outcome::Failure
+            };
+            let mut notification =
+                notification(outcome, &Some(tail.to_text())).exit_code(status.code());
+            if let Some(m) = &exit_match {
+                notification = m.annotate(notification);
+            } else if outcome == Outcome::Failure {
+                notification = notification
+                    .with_error("Command exited before anything matched".to_owned());
+            }
+            notifier.send(&notification)?;
+            outcome
+        }
+    };
+    drop(forwarder);
+
+    if outcome == Outcome::Success {
+        Ok(())
+    } else {
+        Err(Error::CommandFailedOrTimedOut { status: None })
+    }
+}
+
+/// Prepare output for comparison with an earlier run, removing any text
+/// which matches `ignore`, and any trailing whitespace.
+fn normalize(lines: &[Line], ignore: &[Regex]) -> Vec<String> {
+    let mut normalized = lines
+        .iter()
+        .map(|line| {
+            let text = ignore.iter().fold(line.text.clone(), |text, re| {
+                re.replace_all(&text, "").into_owned()
+            });
+            text.trim_end().to_owned()
+        })
+        .collect::<Vec<_>>();
+    while normalized.last().is_some_and(|line| line.is_empty()) {
+        normalized.pop();
+    }
+    normalized
+}
+
+/// The output of a single run of a command.
+struct RunOutput {
+    status: process::ExitStatus,
+    /// Lines from both standard output and standard error, in the order we
+    /// received them.
+    lines: Vec<Line>,
+}
+
+/// Run a command a single time, and return its output. If `echo` is true,
+/// we print the output as it appears.
+fn run_once(cmd: &Command, echo: bool) -> Result<RunOutput> {
+    let mut child = child::spawn_piped(cmd, true)?;
+    let _forwarder = child::forward_signals(&child, true);
+    let mut lines = vec![];
+    for line in output::read_lines(&mut child) {
+        if echo {
+            line.echo()?;
+        }
+        lines.push(line);
+    }
+    let status = child::wait(&mut child, cmd)?;
+    Ok(RunOutput { status, lines })
 }
@@ -0,0 +1,87 @@
+//! Our `alert watch-file` subcommand.
+
+use std::path::PathBuf;
+use std::time;
+use structopt::StructOpt;
+
+use crate::child;
+use crate::config::Config;
+use crate::errors::*;
+use crate::follow::follow_file;
+use crate::matcher::MatchOpt;
+use crate::notify::{Notifier, NotifyOpt, Outcome};
+use crate::output::Tail;
+
+/// How many lines of output should we make available to templates?
+const OUTPUT_TAIL_LINES: usize = 10;
+
+/// Options for `watch-file`.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Follows a log file and watches for output")]
+pub struct Opt {
+    #[structopt(flatten)]
+    matcher: MatchOpt,
+
+    /// Check the existing contents of the file, too, instead of only new
+    /// lines.
+    #[structopt(long = "from-start")]
+    from_start: bool,
+
+    #[structopt(flatten)]
+    notify: NotifyOpt,
+
+    /// The file to follow. It doesn't need to exist yet.
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+}
+
+pub fn run(opt: &Opt, config: &Config, notifier: &dyn Notifier) -> Result<()> {
+    let start = time::SystemTime::now();
+    let matcher = opt.matcher.matcher(config)?;
+    let end = matcher.deadline(start);
+
+    // Notice Ctrl-C and friends, so that we can tell the user why we stopped.
+    let _signals = child::catch_signals();
+    let (lines, follower) = follow_file(opt.path.clone(), opt.from_start);
+    let mut tail = Tail::new(OUTPUT_TAIL_LINES);
+    let matched = matcher.wait_for_match(&lines, end, &mut tail)?;
+    let signal = child::received_signal();
+    // Otherwise, we only run out of lines if we couldn't read the file.
+    let outcome = match (&matched, signal) {
+        (Some(m), _) => m.outcome,
+        (None, Some(_)) => Outcome::Interrupted,
+        (None, None) => Outcome::Error,
+    };
+
+    let notification = opt
+        .notify
+        .notification(config, outcome)
+        .with_log_file(opt.path.clone())
+        .with_subject("Job")
+        .duration(start.elapsed().unwrap_or_default())
+        .output_tail(tail.to_text());
+    let notification = match &matched {
+        Some(m) => m.annotate(notification),
+        None if signal.is_some() => notification.signal(signal),
+        None => {
+            let error = match follower.join() {
+                Ok(Err(err)) => {
+                    format!("Could not read {} ({})", opt.path.display(), err)
+                }
+                _ => format!("Could not read {}", opt.path.display()),
+            };
+            notification.with_error(error)
+        }
+    };
+    notifier.send(&notification)?;
+    if let (None, Some(signal)) = (&matched, signal) {
+        return Err(Error::CommandFailedOrTimedOut {
+            status: child::signal_status(signal),
+        });
+    }
+    if outcome == Outcome::Success {
+        Ok(())
+    } else {
+        Err(Error::CommandFailedOrTimedOut { status: None })
+    }
+}
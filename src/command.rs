@@ -26,6 +26,21 @@ impl Command {
             })
         }
     }
+
+    /// Create a `Command` from a line typed into an interactive shell. We
+    /// keep the entire line in `cmd`, so that it's displayed exactly as the
+    /// user typed it.
+    pub fn from_shell_line(line: &str) -> Result<Command> {
+        let line = line.trim();
+        if line.is_empty() {
+            Err(Error::NoCommandSpecified)
+        } else {
+            Ok(Command {
+                cmd: line.to_owned(),
+                args: vec![],
+            })
+        }
+    }
 }
 
 impl fmt::Display for Command {
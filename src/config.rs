@@ -1,19 +1,324 @@
 //! Configuration support.
 
+use log::debug;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::errors::*;
+use crate::notify::{Outcome, Priority, Template, Templates};
+use crate::util::env_var;
 
 /// A parsed version of our configuration file.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// Configuration for each notifier.
+    #[serde(default)]
     notifiers: HashMap<String, NotifierConfig>,
+    /// Templates for notification titles and messages, used by all
+    /// notifiers.
+    #[serde(default)]
+    templates: Templates,
+    /// Custom fields which can be used in templates.
+    #[serde(default)]
+    fields: HashMap<String, String>,
+    /// Priorities to use for each outcome, overriding our defaults.
+    #[serde(default)]
+    priorities: HashMap<Outcome, Priority>,
+    /// Defaults for `alert run`.
+    #[serde(default)]
+    run: RunConfig,
+    /// Settings for `alert watch` and `alert watch-file`.
+    #[serde(default)]
+    watch: WatchConfig,
+}
+
+impl Config {
+    /// Load our configuration file. If `ALERT_CONFIG` is set, we require that
+    /// the file exists. Otherwise, we look for `alert/config.toml` in the
+    /// user's config directory, and use a default configuration if there's
+    /// nothing there.
+    pub fn load() -> Result<Config> {
+        let (path, required) = match env::var_os("ALERT_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => match default_config_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        debug!("Loading configuration from {}", path.display());
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                debug!("No configuration file, using defaults");
+                return Ok(Config::default());
+            }
+            Err(source) => return Err(Error::CouldNotReadConfig { path, source }),
+        };
+        toml::from_str(&data)
+            .map_err(|source| Error::CouldNotParseConfig { path, source })
+    }
+
+    /// Look up the configuration for the named notifier.
+    pub fn notifier(&self, name: &str) -> Option<&NotifierConfig> {
+        self.notifiers.get(name)
+    }
+
+    /// Templates shared by all notifiers.
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// Custom fields which can be used in templates.
+    pub fn fields(&self) -> &HashMap<String, String> {
+        &self.fields
+    }
+
+    /// The priority to use for `outcome`, if the user configured one.
+    pub fn priority(&self, outcome: Outcome) -> Option<Priority> {
+        self.priorities.get(&outcome).cloned()
+    }
+
+    /// Is `priority` configured for any outcome?
+    pub fn uses_priority(&self, priority: Priority) -> bool {
+        self.priorities.values().any(|&p| p == priority)
+    }
+
+    /// Defaults for `alert run`.
+    pub fn run(&self) -> &RunConfig {
+        &self.run
+    }
+
+    /// Settings for `alert watch` and `alert watch-file`.
+    pub fn watch(&self) -> &WatchConfig {
+        &self.watch
+    }
+}
+
+/// Defaults for `alert run`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    /// Don't notify about commands which finish faster than this.
+    #[serde(default, with = "optional_duration")]
+    min_duration: Option<Duration>,
+    /// Should we notify about failures even if they're faster than
+    /// `min_duration`?
+    #[serde(default = "default_notify_short_failures")]
+    notify_short_failures: bool,
+}
+
+impl RunConfig {
+    /// Don't notify about commands which finish faster than this.
+    pub fn min_duration(&self) -> Option<Duration> {
+        self.min_duration
+    }
+
+    /// Should we notify about failures even if they're faster than
+    /// `min_duration`?
+    pub fn notify_short_failures(&self) -> bool {
+        self.notify_short_failures
+    }
+}
+
+impl Default for RunConfig {
+    fn default() -> RunConfig {
+        RunConfig {
+            min_duration: None,
+            notify_short_failures: default_notify_short_failures(),
+        }
+    }
+}
+
+/// Settings for `alert watch` and `alert watch-file`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Rules which are checked in addition to any on the command line.
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+}
+
+impl WatchConfig {
+    /// Rules which are checked in addition to any on the command line.
+    pub fn rules(&self) -> &[RuleConfig] {
+        &self.rules
+    }
+}
+
+/// A rule for recognizing interesting output.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// The name of this rule, available to templates as `{rule}`.
+    name: String,
+    /// The outcome to report when this rule matches.
+    outcome: Outcome,
+    /// A regular expression to look for.
+    pattern: String,
+    /// The stream to check: `stdout`, `stderr` or `both`.
+    stream: Option<String>,
+    /// A template for the notification message.
+    message: Option<Template>,
+}
+
+impl RuleConfig {
+    /// The name of this rule.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The outcome to report when this rule matches.
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// A regular expression to look for.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The stream to check, if the user specified one.
+    pub fn stream(&self) -> Option<&str> {
+        self.stream.as_deref()
+    }
+
+    /// A template for the notification message.
+    pub fn message(&self) -> Option<&Template> {
+        self.message.as_ref()
+    }
 }
 
 /// Per-backend configuration.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct NotifierConfig {
+    #[serde(default = "default_enabled")]
     enabled: bool,
     backend: Option<String>,
+    #[serde(default)]
     options: HashMap<String, String>,
+    /// Templates which override our global templates for this notifier.
+    #[serde(default)]
+    templates: Templates,
+    /// If present, only send notifications with these outcomes.
+    outcomes: Option<Vec<Outcome>>,
+}
+
+impl NotifierConfig {
+    /// Has the user enabled this notifier?
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The name of the backend to use, if it differs from the notifier name.
+    pub fn backend(&self) -> Option<&str> {
+        self.backend.as_deref()
+    }
+
+    /// Look up a backend-specific option.
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(|v| &v[..])
+    }
+
+    /// Look up and parse a backend-specific option.
+    pub fn parse_option<T: FromStr>(&self, key: &str) -> Result<Option<T>> {
+        match self.option(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| Error::InvalidOption {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Look up a backend-specific option, falling back to the environment
+    /// variable `env_name` if it isn't set.
+    pub fn option_or_env(&self, key: &str, env_name: &str) -> Result<String> {
+        match self.option(key) {
+            Some(value) => Ok(value.to_owned()),
+            None => env_var(env_name),
+        }
+    }
+
+    /// Templates used only by this notifier.
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    /// The outcomes this notifier should send, if the user limited them.
+    pub fn outcomes(&self) -> Option<&[Outcome]> {
+        self.outcomes.as_deref()
+    }
+}
+
+impl Default for NotifierConfig {
+    fn default() -> NotifierConfig {
+        NotifierConfig {
+            enabled: default_enabled(),
+            backend: None,
+            options: HashMap::new(),
+            templates: Templates::default(),
+            outcomes: None,
+        }
+    }
+}
+
+/// (De)serialize an optional duration as a string like `"30s"`, so that we
+/// report bad durations when we load the config file.
+mod optional_duration {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    use crate::util::parse_duration;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) => parse_duration(&s).map(Some).map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
+
+    pub fn serialize<S>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => serializer
+                .serialize_str(&humantime::format_duration(*duration).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Notifiers are enabled unless the user says otherwise.
+fn default_enabled() -> bool {
+    true
+}
+
+/// We tell the user about quick failures unless they say otherwise.
+fn default_notify_short_failures() -> bool {
+    true
+}
+
+/// Where should we look for our config file by default?
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("alert").join("config.toml"))
 }
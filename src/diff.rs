@@ -0,0 +1,112 @@
+//! Compact line-by-line diffs, for telling the user what changed.
+
+/// Above this many cells, our diff table would use too much memory, so we
+/// just report everything in the middle as changed.
+const MAX_TABLE_SIZE: usize = 1_000_000;
+
+/// Compare `old` and `new`, returning the lines which were removed (prefixed
+/// with `-`) and added (prefixed with `+`). We show at most `max_lines`
+/// lines, followed by a count of any others.
+pub fn diff(old: &[String], new: &[String], max_lines: usize) -> String {
+    let mut changes = changes(old, new)
+        .into_iter()
+        .map(|change| match change {
+            Change::Removed(i) => format!("-{}", old[i]),
+            Change::Added(j) => format!("+{}", new[j]),
+        })
+        .collect::<Vec<_>>();
+    if changes.len() > max_lines {
+        let more = changes.len() - max_lines;
+        changes.truncate(max_lines);
+        changes.push(format!("({} more changed lines)", more));
+    }
+    changes.join("\n")
+}
+
+/// For each line in `new`, was it added since `old`?
+pub fn added_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<bool> {
+    let mut added = vec![false; new.len()];
+    for change in changes(old, new) {
+        if let Change::Added(j) = change {
+            added[j] = true;
+        }
+    }
+    added
+}
+
+/// A line removed from `old`, or added to `new`, by its index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Removed(usize),
+    Added(usize),
+}
+
+/// Find the smallest set of changes which turns `old` into `new`, in order.
+fn changes<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    // Skip anything which is the same at the start or end.
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    let mut changes = vec![];
+    if old.len() * new.len() > MAX_TABLE_SIZE {
+        changes.extend((0..old.len()).map(|i| Change::Removed(prefix + i)));
+        changes.extend((0..new.len()).map(|j| Change::Added(prefix + j)));
+    } else {
+        // `lcs[i][j]` is the length of the longest common subsequence of
+        // `old[i..]` and `new[j..]`.
+        let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+            } else if j == new.len()
+                || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1])
+            {
+                changes.push(Change::Removed(prefix + i));
+                i += 1;
+            } else {
+                changes.push(Change::Added(prefix + j));
+                j += 1;
+            }
+        }
+    }
+    changes
+}
+
+#[test]
+fn diffs_show_changed_lines() {
+    let lines = |s: &str| s.split(' ').map(|l| l.to_owned()).collect::<Vec<_>>();
+    assert_eq!(diff(&lines("a b c"), &lines("a x c"), 10), "-b\n+x");
+    assert_eq!(diff(&lines("a b c"), &lines("a b c d"), 10), "+d");
+    assert_eq!(diff(&lines("a b c"), &lines("b c"), 10), "-a");
+    assert_eq!(
+        diff(&lines("a b c d"), &lines("w x y z"), 3),
+        "-a\n-b\n-c\n(5 more changed lines)"
+    );
+}
+
+#[test]
+fn added_lines_are_found() {
+    assert_eq!(
+        added_lines(&["a", "b", "c"], &["a", "new", "b", "c"]),
+        vec![false, true, false, false]
+    );
+    assert_eq!(added_lines(&["a", "b"], &["b", "x"]), vec![false, true]);
+}
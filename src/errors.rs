@@ -3,6 +3,7 @@
 use std::env;
 use std::io;
 use std::num;
+use std::path::PathBuf;
 use std::process::ExitStatus;
 use thiserror::Error;
 
@@ -39,6 +40,33 @@ pub enum Error {
     #[error("Command failed or timed out with status {:?}", .status)]
     CommandFailedOrTimedOut { status: Option<ExitStatus> },
 
+    /// We could not parse information about a process.
+    #[error("Could not parse information about process {}", .pid)]
+    CouldNotParseProcess { pid: u32 },
+
+    /// We could not parse our configuration file.
+    #[error("Could not parse {} ({})", .path.display(), .source)]
+    CouldNotParseConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    /// We could not read a file attached to a notification.
+    #[error("Could not read attachment {} ({})", .path.display(), .source)]
+    CouldNotReadAttachment { path: PathBuf, source: io::Error },
+
+    /// We could not read information about a process.
+    #[error("Could not read {} ({})", .path.display(), .source)]
+    CouldNotReadProcess { path: PathBuf, source: io::Error },
+
+    /// We could not read our configuration file.
+    #[error("Could not read {} ({})", .path.display(), .source)]
+    CouldNotReadConfig { path: PathBuf, source: io::Error },
+
+    /// We could not read from standard input.
+    #[error("Could not read from standard input ({})", .source)]
+    CouldNotReadStdin { source: io::Error },
+
     /// An error occurred running an external command.
     #[error("Could not run {} ({})", .cmd, .source)]
     CouldNotRun { cmd: Command, source: io::Error },
@@ -47,6 +75,14 @@ pub enum Error {
     #[error("Could not send notification using {}", .service)]
     CouldNotSendNotification { service: String },
 
+    /// We could not wait for a process to exit.
+    #[error("Could not wait for process {} ({})", .pid, .source)]
+    CouldNotWaitForProcess { pid: u32, source: io::Error },
+
+    /// We could not save a command's output.
+    #[error("Could not write {} ({})", .path.display(), .source)]
+    CouldNotWriteOutput { path: PathBuf, source: io::Error },
+
     /// We could not write to either stdout or stderr.
     #[error("Could not write to {} ({})", .dest, .source)]
     CouldNotWriteToStdio {
@@ -59,11 +95,113 @@ pub enum Error {
     #[error("Could not read environment variable {} ({})", .name, .source)]
     Env { name: String, source: env::VarError },
 
+    /// A numeric condition could not be parsed.
+    #[error("Invalid condition {:?} (try \"<= 0\" or \"> 1000\")", .condition)]
+    InvalidCondition { condition: String },
+
+    /// A duration could not be parsed.
+    #[error("Invalid duration {:?} (try \"90\", \"30s\" or \"2h 30m\")", .value)]
+    InvalidDuration { value: String },
+
+    /// A set of exit codes could not be parsed.
+    #[error("Invalid exit codes {:?} (try \"1\" or \"1,3,10-20\")", .value)]
+    InvalidExitCodes { value: String },
+
+    /// A custom field was not of the form `KEY=VALUE`.
+    #[error("Expected KEY=VALUE, found {:?}", .field)]
+    InvalidField { field: String },
+
+    /// A `--hysteresis` amount was negative or not a number.
+    #[error("Invalid hysteresis {:?} (expected a number of at least 0)", .value)]
+    InvalidHysteresis { value: String },
+
+    /// A JSON query could not be parsed.
+    #[error("Invalid JSON query {:?} ({})", .query, .reason)]
+    InvalidJsonQuery { query: String, reason: String },
+
+    /// A notifier option had an invalid value.
+    #[error("Invalid value for option {}: {:?}", .key, .value)]
+    InvalidOption { key: String, value: String },
+
+    /// A `--rule` was not of the form `NAME[@STREAM]=REGEX`.
+    #[error("Expected NAME[@STREAM]=REGEX, found {:?}", .rule)]
+    InvalidRule { rule: String },
+
+    /// A `--rule-outcome` was not of the form `NAME=OUTCOME`.
+    #[error("Expected NAME=OUTCOME, found {:?}", .spec)]
+    InvalidRuleOutcomeSpec { spec: String },
+
+    /// A `--rule-message` was not of the form `NAME=TEMPLATE`.
+    #[error("Expected NAME=TEMPLATE, found {:?}", .message)]
+    InvalidRuleMessage { message: String },
+
+    /// A rule tried to report an outcome which only `alert` itself reports.
+    #[error(
+        "Rule {:?} can't report {:?} (use success, failure, timeout, interrupted or error)",
+        .name,
+        .outcome
+    )]
+    InvalidRuleOutcome { name: String, outcome: String },
+
+    /// A template could not be parsed.
+    #[error("Invalid template {:?} ({})", .template, .reason)]
+    InvalidTemplate { template: String, reason: String },
+
+    /// A notifier was not configured correctly.
+    #[error("Invalid configuration for {}: {}", .service, .reason)]
+    InvalidNotifierConfig { service: String, reason: String },
+
+    /// No running process matched a pattern.
+    #[error("No running process matches {:?}", .pattern)]
+    NoMatchingProcess { pattern: String },
+
     /// No command to run was specified.
     #[error("No command to run was specified")]
     NoCommandSpecified,
 
+    /// The user requested a notifier which is disabled in the config file.
+    #[error("Notifier {} is disabled", .name)]
+    NotifierDisabled { name: String },
+
+    /// A capture group has the same name as one of our template variables,
+    /// so templates couldn't use it.
+    #[error(
+        "Capture group {:?} in /{}/ would hide the template variable of the same name",
+        .name,
+        .pattern
+    )]
+    ReservedCaptureName { name: String, pattern: String },
+
+    /// More than one running process matched a pattern.
+    #[error("More than one process matches {:?}: {:?}", .pattern, .pids)]
+    TooManyMatchingProcesses { pattern: String, pids: Vec<u32> },
+
     /// The user requested an unknown notification backend.
     #[error("Unknown notifier: {}", .name)]
     UnknownNotifier { name: String },
+
+    /// The user specified a backoff strategy we don't support.
+    #[error("Unknown backoff: {} (expected constant or exponential)", .name)]
+    UnknownBackoff { name: String },
+
+    /// A `--rule-outcome` or `--rule-message` refers to a `--rule` which
+    /// doesn't exist.
+    #[error("No --rule is named {:?}", .name)]
+    UnknownRule { name: String },
+
+    /// The user specified a shell we don't support.
+    #[error("Unknown shell: {}", .name)]
+    UnknownShell { name: String },
+
+    /// The user specified an output stream we don't know about.
+    #[error("Unknown stream: {} (expected stdout, stderr or both)", .name)]
+    UnknownStream { name: String },
+
+    /// The user specified an unknown outcome.
+    #[error("Unknown outcome: {}", .name)]
+    UnknownOutcome { name: String },
+
+    /// The user specified an unknown priority.
+    #[error("Unknown priority: {}", .name)]
+    UnknownPriority { name: String },
 }
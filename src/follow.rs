@@ -0,0 +1,327 @@
+//! Following a file as it grows, like `tail -F`. We notice when the file is
+//! truncated, or when it's replaced by a new file because of log rotation.
+
+use log::debug;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::output::{Line, Stream};
+
+/// How often should we check the file if we can't ask the OS to tell us
+/// about changes?
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Even if the OS tells us about changes, check occasionally, in case we
+/// missed something.
+#[cfg(target_os = "linux")]
+const NOTIFY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Follow `path` in a background thread, sending each new line to the
+/// returned channel. If `from_start` is true, we begin with the existing
+/// contents of the file. Lines are reported as standard output. If we can't
+/// read the file, the channel is closed, and joining the thread returns the
+/// error.
+pub fn follow_file(
+    path: PathBuf,
+    from_start: bool,
+) -> (Receiver<Line>, JoinHandle<io::Result<()>>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut follower = Follower::new(path, from_start);
+        let result = follower.run(&tx);
+        if let Err(err) = &result {
+            debug!("Stopped following {}: {}", follower.path.display(), err);
+        }
+        result
+    });
+    (rx, handle)
+}
+
+/// The file we're currently reading.
+struct OpenFile {
+    reader: BufReader<File>,
+    /// An identifier for the file, so we can notice when it's replaced.
+    id: FileId,
+    /// How far we've read.
+    position: u64,
+}
+
+/// State for following a file.
+struct Follower {
+    path: PathBuf,
+    /// Should we read the existing contents of the first file we open?
+    from_start: bool,
+    file: Option<OpenFile>,
+    /// Any partial line we've read.
+    partial: Vec<u8>,
+    waiter: Waiter,
+}
+
+impl Follower {
+    fn new(path: PathBuf, from_start: bool) -> Follower {
+        let waiter = Waiter::new(&path);
+        Follower {
+            path,
+            from_start,
+            file: None,
+            partial: vec![],
+            waiter,
+        }
+    }
+
+    /// Follow our file until our receiver goes away.
+    fn run(&mut self, tx: &Sender<Line>) -> io::Result<()> {
+        loop {
+            if self.file.is_none() {
+                self.open()?;
+            }
+            if !self.read_lines(tx)? || !self.check_for_rotation(tx)? {
+                return Ok(());
+            }
+            self.waiter.wait();
+        }
+    }
+
+    /// Try to open our file, if it exists.
+    fn open(&mut self) -> io::Result<()> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                // When the file appears, all of it will be new.
+                self.from_start = true;
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        let id = FileId::for_file(&file)?;
+        // The first time we open the file, skip what's already there unless
+        // we were asked not to. Any file that appears later is new, so we
+        // read all of it.
+        let position = if self.from_start {
+            0
+        } else {
+            file.seek(SeekFrom::End(0))?
+        };
+        self.from_start = true;
+        debug!("Following {} from {}", self.path.display(), position);
+        self.file = Some(OpenFile {
+            reader: BufReader::new(file),
+            id,
+            position,
+        });
+        Ok(())
+    }
+
+    /// Send any complete lines to `tx`. Returns false if nobody is listening.
+    fn read_lines(&mut self, tx: &Sender<Line>) -> io::Result<bool> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(true),
+        };
+        loop {
+            let count = file.reader.read_until(b'\n', &mut self.partial)?;
+            if count == 0 {
+                return Ok(true);
+            }
+            file.position += count as u64;
+            if self.partial.ends_with(b"\n") {
+                let text = String::from_utf8_lossy(&self.partial)
+                    .trim_end_matches(&['\n', '\r'][..])
+                    .to_owned();
+                self.partial.clear();
+                let line = Line {
+                    stream: Stream::Stdout,
+                    text,
+                };
+                if tx.send(line).is_err() {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /// Reopen or rewind our file if it has been replaced or truncated.
+    /// Returns false if nobody is listening.
+    fn check_for_rotation(&mut self, tx: &Sender<Line>) -> io::Result<bool> {
+        let (id, position) = match &self.file {
+            Some(file) => (file.id, file.position),
+            None => return Ok(true),
+        };
+        match fs::metadata(&self.path) {
+            Ok(metadata) if FileId::for_metadata(&metadata) != id => {
+                // The old file may have grown since we last read it, so
+                // finish reading it before we switch to the new one.
+                debug!("{} was replaced", self.path.display());
+                if !self.read_lines(tx)? {
+                    return Ok(false);
+                }
+                self.file = None;
+                self.partial.clear();
+                self.open()?;
+            }
+            Ok(metadata) if metadata.len() < position => {
+                debug!("{} was truncated", self.path.display());
+                if let Some(file) = &mut self.file {
+                    file.reader.seek(SeekFrom::Start(0))?;
+                    file.position = 0;
+                }
+                self.partial.clear();
+            }
+            Ok(_) => {}
+            // The file was moved away, and the new one doesn't exist yet.
+            // Keep reading the old one until it does.
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        Ok(true)
+    }
+}
+
+/// Identifies a specific file, even if it's renamed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    fn for_file(file: &File) -> io::Result<FileId> {
+        Ok(FileId::for_metadata(&file.metadata()?))
+    }
+
+    #[cfg(unix)]
+    fn for_metadata(metadata: &fs::Metadata) -> FileId {
+        use std::os::unix::fs::MetadataExt;
+        FileId {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        }
+    }
+
+    /// We can't identify files on this platform, so we only notice
+    /// truncation.
+    #[cfg(not(unix))]
+    fn for_metadata(_metadata: &fs::Metadata) -> FileId {
+        FileId { dev: 0, ino: 0 }
+    }
+}
+
+/// Waits until the file we're following might have changed. On Linux, we
+/// use `inotify` to watch the file's directory, and we fall back to polling
+/// everywhere else.
+struct Waiter {
+    #[cfg(target_os = "linux")]
+    inotify: Option<libc::c_int>,
+}
+
+impl Waiter {
+    #[cfg(target_os = "linux")]
+    fn new(path: &Path) -> Waiter {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let dir = match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(dir) => dir,
+            Err(_) => return Waiter { inotify: None },
+        };
+        // SAFETY: We pass a valid C string, and close the descriptor if we
+        // can't use it.
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Waiter { inotify: None };
+            }
+            let mask = libc::IN_MODIFY
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_FROM
+                | libc::IN_MOVED_TO;
+            if libc::inotify_add_watch(fd, dir.as_ptr(), mask) < 0 {
+                debug!("Could not watch {:?}, polling instead", dir);
+                libc::close(fd);
+                return Waiter { inotify: None };
+            }
+            Waiter { inotify: Some(fd) }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new(_path: &Path) -> Waiter {
+        Waiter {}
+    }
+
+    /// Wait until something happens, or for a short while.
+    #[cfg(target_os = "linux")]
+    fn wait(&self) {
+        let fd = match self.inotify {
+            Some(fd) => fd,
+            None => return thread::sleep(POLL_INTERVAL),
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let mut buf = [0u8; 4096];
+        // SAFETY: `pollfd` and `buf` are valid for the duration of the calls.
+        unsafe {
+            libc::poll(&mut pollfd, 1, NOTIFY_CHECK_INTERVAL.as_millis() as _);
+            // We don't care what happened, so just throw the events away.
+            while libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) > 0
+            {
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wait(&self) {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(fd) = self.inotify {
+            // SAFETY: We own this descriptor.
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+#[test]
+fn rotation_finishes_reading_the_old_file() {
+    let dir =
+        std::env::temp_dir().join(format!("alert-follow-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("test.log");
+    fs::write(&path, "first\n").unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut follower = Follower::new(path.clone(), true);
+    follower.open().unwrap();
+    assert!(follower.read_lines(&tx).unwrap());
+
+    // Write to the old file after our last read, then rotate it.
+    let mut old = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(old, "last words").unwrap();
+    fs::rename(&path, dir.join("test.log.1")).unwrap();
+    fs::write(&path, "second\n").unwrap();
+    assert!(follower.check_for_rotation(&tx).unwrap());
+    assert!(follower.read_lines(&tx).unwrap());
+
+    drop(tx);
+    let lines = rx.iter().map(|line| line.text).collect::<Vec<_>>();
+    assert_eq!(lines, vec!["first", "last words", "second"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,244 @@
+//! Path comparisons for checking JSON output.
+//!
+//! This is not `jq`. A query is a path like `.status.phase`, `.items[0]` or
+//! `.["odd key"]`, optionally followed by one of the comparison operators
+//! `==`, `!=`, `<`, `<=`, `>` or `>=` and a JSON literal. A path on its own
+//! matches unless it's missing, `false` or `null`. Like `jq`, missing fields
+//! are `null`.
+
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::*;
+
+/// A parsed query.
+#[derive(Clone, Debug)]
+pub struct JsonQuery {
+    /// The original query, for display.
+    source: String,
+    path: Vec<Key>,
+    comparison: Option<(Op, Value)>,
+}
+
+impl JsonQuery {
+    /// Does `value` satisfy this query?
+    pub fn is_match(&self, value: &Value) -> bool {
+        let found = self
+            .path
+            .iter()
+            .try_fold(value, |value, key| match key {
+                Key::Field(name) => value.get(name),
+                Key::Index(index) => value.get(index),
+            })
+            .unwrap_or(&Value::Null);
+        match &self.comparison {
+            Some((op, literal)) => op.matches(compare(found, literal)),
+            None => !matches!(found, Value::Null | Value::Bool(false)),
+        }
+    }
+}
+
+impl FromStr for JsonQuery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<JsonQuery> {
+        let invalid = |reason: &str| Error::InvalidJsonQuery {
+            query: s.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let (path, rest) = parse_path(s.trim_start()).map_err(invalid)?;
+        let rest = rest.trim();
+        let comparison = if rest.is_empty() {
+            None
+        } else {
+            let (op, literal) = Op::strip_prefix(rest)
+                .ok_or_else(|| invalid("expected a comparison operator"))?;
+            let literal = serde_json::from_str(literal)
+                .map_err(|err| invalid(&format!("invalid literal: {}", err)))?;
+            Some((op, literal))
+        };
+        Ok(JsonQuery {
+            source: s.to_owned(),
+            path,
+            comparison,
+        })
+    }
+}
+
+impl fmt::Display for JsonQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+/// One step along a path.
+#[derive(Clone, Debug, PartialEq)]
+enum Key {
+    Field(String),
+    Index(usize),
+}
+
+/// Parse a path from the start of `s`, returning the keys and whatever
+/// follows the path.
+fn parse_path(s: &str) -> std::result::Result<(Vec<Key>, &str), &'static str> {
+    let mut rest = s.strip_prefix('.').ok_or("expected a path like .name")?;
+    let mut keys = vec![];
+    // Allow `.foo`, `."foo"` or `.[0]` straight after a dot.
+    let mut after_dot = true;
+    loop {
+        if after_dot && rest.starts_with('"') {
+            let (key, after) = parse_string(rest)?;
+            keys.push(Key::Field(key));
+            rest = after;
+        } else if after_dot
+            && rest.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            keys.push(Key::Field(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let after = after.trim_start();
+            let (key, after) = if after.starts_with('"') {
+                let (key, after) = parse_string(after)?;
+                (Key::Field(key), after)
+            } else {
+                let end = after
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(after.len());
+                let index = after[..end]
+                    .parse()
+                    .map_err(|_| "expected an index or a key")?;
+                (Key::Index(index), &after[end..])
+            };
+            keys.push(key);
+            rest = after.trim_start().strip_prefix(']').ok_or("expected ']'")?;
+        } else if let Some(after) = rest.strip_prefix('.') {
+            rest = after;
+            after_dot = true;
+            continue;
+        } else {
+            return Ok((keys, rest));
+        }
+        after_dot = false;
+    }
+}
+
+/// Parse a JSON string from the start of `s`, returning it and whatever
+/// follows it.
+fn parse_string(s: &str) -> std::result::Result<(String, &str), &'static str> {
+    let mut strings = serde_json::Deserializer::from_str(s).into_iter::<String>();
+    match strings.next() {
+        Some(Ok(string)) => Ok((string, &s[strings.byte_offset()..])),
+        _ => Err("invalid string"),
+    }
+}
+
+/// A comparison operator, also used for checking numbers extracted from
+/// output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// All our operators, with the two-character ones first so that they're
+    /// checked before their prefixes.
+    const ALL: [Op; 6] = [Op::Eq, Op::Ne, Op::Le, Op::Ge, Op::Lt, Op::Gt];
+
+    /// The operator as the user would write it.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+
+    /// Split an operator off the start of `s`.
+    pub fn strip_prefix(s: &str) -> Option<(Op, &str)> {
+        Op::ALL
+            .iter()
+            .find_map(|&op| Some((op, s.strip_prefix(op.symbol())?)))
+    }
+
+    /// Compare two numbers.
+    pub fn compare_numbers(self, left: f64, right: f64) -> bool {
+        self.matches(left.partial_cmp(&right))
+    }
+
+    /// Is a comparison which came out as `ordering` a match? `None` means
+    /// the values couldn't be compared.
+    fn matches(self, ordering: Option<Ordering>) -> bool {
+        match self {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/// Compare two values. Numbers are compared numerically, so `1 == 1.0`.
+/// Values of different types are never equal, and only numbers and strings
+/// can be ordered.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64()?.partial_cmp(&r.as_f64()?),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        (l, r) if l == r => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[test]
+fn queries_check_json() {
+    let value: Value = serde_json::from_str(
+        r#"{"status": {"phase": "Succeeded", "ready": 3, "odd key": true},
+            "items": [{"name": "a"}, {"name": "b"}]}"#,
+    )
+    .unwrap();
+    let check = |query: &str| query.parse::<JsonQuery>().unwrap().is_match(&value);
+    assert!(check(r#".status.phase == "Succeeded""#));
+    assert!(!check(r#".status.phase != "Succeeded""#));
+    assert!(check(".status.ready >= 3"));
+    assert!(!check(".status.ready < 3"));
+    assert!(check(".status.ready == 3.0"));
+    assert!(check(r#".items[1].name == "b""#));
+    assert!(check(r#".items[ 0 ]["name"]=="a""#));
+    assert!(check(r#".status["odd key"]"#));
+    assert!(check(r#"."status"."odd key""#));
+    assert!(check(".missing == null"));
+    assert!(!check(".missing.deeper"));
+    assert!(check(r#".items[0] == {"name": "a"}"#));
+}
+
+#[test]
+fn invalid_queries_are_rejected() {
+    for query in &[
+        "",
+        ".a ==",
+        ".a = 1",
+        ".a == 1 and .b",
+        "\"unclosed",
+        ".a[",
+        ".a[-1]",
+        "(.a)",
+        "bogus",
+    ] {
+        assert!(query.parse::<JsonQuery>().is_err(), "{:?}", query);
+    }
+}
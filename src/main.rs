@@ -1,17 +1,33 @@
-use log::debug;
+use clap::AppSettings;
+use log::{debug, warn};
 use std::process;
 use structopt::StructOpt;
 
+mod child;
 mod cmd_run;
+mod cmd_send;
+mod cmd_shell_hook;
+mod cmd_shell_init;
+mod cmd_wait;
 mod cmd_watch;
+mod cmd_watch_file;
 mod command;
 mod config;
+mod diff;
 mod errors;
+mod follow;
+mod json_query;
+mod matcher;
 mod notify;
+mod output;
+mod procfs;
+mod schedule;
+mod screen;
 mod util;
 
+use crate::config::Config;
 use crate::errors::*;
-use crate::notify::choose_notifier;
+use crate::notify::{choose_notifier, Notifier};
 
 /// Our command-line options.
 #[derive(Debug, StructOpt)]
@@ -26,7 +42,38 @@ enum Opt {
     /// Runs a command repeatedly and watches for output.
     Watch {
         #[structopt(flatten)]
-        watch_opt: cmd_watch::Opt,
+        watch_opt: Box<cmd_watch::Opt>,
+    },
+
+    /// Follows a log file and watches for output.
+    WatchFile {
+        #[structopt(flatten)]
+        watch_file_opt: cmd_watch_file::Opt,
+    },
+
+    /// Sends a notification without running a command.
+    Send {
+        #[structopt(flatten)]
+        send_opt: cmd_send::Opt,
+    },
+
+    /// Waits for a running process and notifies when it finishes.
+    Wait {
+        #[structopt(flatten)]
+        wait_opt: cmd_wait::Opt,
+    },
+
+    /// Prints shell hooks which notify about slow commands.
+    ShellInit {
+        #[structopt(flatten)]
+        shell_init_opt: cmd_shell_init::Opt,
+    },
+
+    /// Reports a command run by an interactive shell.
+    #[structopt(setting(AppSettings::Hidden))]
+    ShellHook {
+        #[structopt(flatten)]
+        shell_hook_opt: cmd_shell_hook::Opt,
     },
 }
 
@@ -39,14 +86,11 @@ fn main() {
         Ok(()) => {}
         Err(Error::CommandFailedOrTimedOut { status }) => {
             // We've already notified the user of this failure, but we still
-            // need to exit with the right status code.
-            //
-            // TODO: We might be able to use Unix-specific Rust APIs to
-            // preserve the exit status when the child process is terminated
-            // by a signal, too.
-            let code = status.and_then(|s| s.code()).unwrap_or(1);
-            debug!("Exiting with code {}", code);
-            process::exit(code);
+            // need to exit the same way as the command.
+            match status {
+                Some(status) => child::exit_like(status),
+                None => process::exit(1),
+            }
         }
         Err(err) => {
             // Our only "wrapper" errors print out the original error, too, so don't
@@ -63,14 +107,53 @@ fn run() -> Result<()> {
     let opt = Opt::from_args();
     debug!("Arguments: {:#?}", opt);
 
-    // Create our notifier _now_ before running any multi-hour subcommands, so
-    // that it has a chance to make sure it's configured correctly while the
-    // user is still watching.
-    let notifier = choose_notifier()?;
-
     // Run a subcommand.
+    let config = match (&opt, Config::load()) {
+        // Don't break the user's shell just because their config is broken.
+        (Opt::ShellInit { .. }, Err(err)) => {
+            warn!("Ignoring config file: {}", err);
+            Config::default()
+        }
+        (_, config) => config?,
+    };
     match &opt {
-        Opt::Run { run_opt } => cmd_run::run(run_opt, notifier.as_ref()),
-        Opt::Watch { watch_opt } => cmd_watch::run(watch_opt, notifier.as_ref()),
+        Opt::Run { run_opt } => {
+            cmd_run::run(run_opt, &config, checked_notifier(&config)?.as_ref())
+        }
+        Opt::Watch { watch_opt } => {
+            cmd_watch::run(watch_opt, &config, checked_notifier(&config)?.as_ref())
+        }
+        Opt::WatchFile { watch_file_opt } => cmd_watch_file::run(
+            watch_file_opt,
+            &config,
+            checked_notifier(&config)?.as_ref(),
+        ),
+        Opt::Send { send_opt } => {
+            cmd_send::run(send_opt, &config, checked_notifier(&config)?.as_ref())
+        }
+        Opt::Wait { wait_opt } => {
+            cmd_wait::run(wait_opt, &config, checked_notifier(&config)?.as_ref())
+        }
+        // Printing shell hooks doesn't need a notifier, and we don't want to
+        // break the user's shell if theirs is misconfigured.
+        Opt::ShellInit { shell_init_opt } => {
+            cmd_shell_init::run(shell_init_opt, &config)
+        }
+        // Shell hooks run in the background after a command has already
+        // finished, so there's nobody to warn early, and checking with a
+        // server first would only slow them down.
+        Opt::ShellHook { shell_hook_opt } => {
+            let notifier = choose_notifier(&config)?;
+            cmd_shell_hook::run(shell_hook_opt, &config, notifier.as_ref())
+        }
     }
 }
+
+/// Create our notifier _now_ before running any multi-hour subcommands, so
+/// that it has a chance to make sure it's configured correctly while the
+/// user is still watching.
+fn checked_notifier(config: &Config) -> Result<Box<dyn Notifier>> {
+    let notifier = choose_notifier(config)?;
+    notifier.validate()?;
+    Ok(notifier)
+}
@@ -0,0 +1,1083 @@
+//! Matching output against the patterns supplied by the user.
+
+use log::warn;
+use regex::Regex;
+use serde_json::Value;
+use std::cell::Cell;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time;
+use structopt::StructOpt;
+
+use crate::child;
+use crate::config::{Config, RuleConfig};
+use crate::errors::*;
+use crate::json_query::{JsonQuery, Op};
+use crate::notify::{Notification, Outcome, Template, BUILTIN_VARIABLES};
+use crate::output::{Line, Stream, Tail};
+use crate::util::parse_duration;
+
+/// Options for deciding when something interesting has happened, shared by
+/// `watch` and `watch-file`.
+#[derive(Debug, StructOpt)]
+pub struct MatchOpt {
+    /// Report success when matching text appears.
+    #[structopt(short = "s", long = "success", value_name = "SUCCESS_REGEX")]
+    success: Option<Regex>,
+
+    /// Report failure when matching text appears.
+    #[structopt(short = "f", long = "failure", value_name = "FAILURE_REGEX")]
+    failure: Option<Regex>,
+
+    /// Report failure when matching text appears, or another outcome chosen
+    /// with --rule-outcome. NAME is available to templates as `{rule}`, and
+    /// named capture groups are available, too. Add "@stdout" or "@stderr"
+    /// to only check one stream. May be given more than once.
+    #[structopt(
+        long = "rule",
+        value_name = "NAME[@STREAM]=REGEX",
+        number_of_values = 1
+    )]
+    rules: Vec<Rule>,
+
+    /// Report OUTCOME instead of failure when the --rule called NAME
+    /// matches.
+    #[structopt(
+        long = "rule-outcome",
+        value_name = "NAME=OUTCOME",
+        number_of_values = 1,
+        parse(try_from_str = parse_rule_outcome)
+    )]
+    rule_outcomes: Vec<(String, Outcome)>,
+
+    /// Use TEMPLATE as the notification message when the --rule called NAME
+    /// matches. It may use the rule's capture groups.
+    #[structopt(
+        long = "rule-message",
+        value_name = "NAME=TEMPLATE",
+        number_of_values = 1,
+        parse(try_from_str = parse_rule_message)
+    )]
+    rule_messages: Vec<(String, Template)>,
+
+    /// Give up if nothing happens after a wait.
+    #[structopt(
+        short = "t",
+        long = "timeout",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration)
+    )]
+    timeout: Option<time::Duration>,
+}
+
+impl MatchOpt {
+    /// Combine our options with any rules in `config`.
+    pub fn matcher(&self, config: &Config) -> Result<Matcher> {
+        let mut rules = vec![];
+        if let Some(failure) = &self.failure {
+            rules.push(Rule::new("failure", Outcome::Failure, failure.clone()));
+        }
+        if let Some(success) = &self.success {
+            rules.push(Rule::new("success", Outcome::Success, success.clone()));
+        }
+        let mut cli_rules = self.rules.clone();
+        for (name, outcome) in &self.rule_outcomes {
+            for rule in rules_named(&mut cli_rules, name)? {
+                rule.outcome = *outcome;
+            }
+        }
+        for (name, message) in &self.rule_messages {
+            for rule in rules_named(&mut cli_rules, name)? {
+                rule.message = Some(message.clone());
+            }
+        }
+        rules.extend(cli_rules);
+        for rule_config in config.watch().rules() {
+            rules.push(Rule::from_config(rule_config)?);
+        }
+        for rule in &rules {
+            rule.check_outcome()?;
+            rule.check_capture_names()?;
+        }
+        // Anything other than success takes priority, so that a failure
+        // can't be hidden by a success on the same line.
+        rules.sort_by_key(|rule| rule.outcome == Outcome::Success);
+        Ok(Matcher {
+            rules,
+            exit_conditions: vec![],
+            json_conditions: vec![],
+            warned_not_json: Cell::new(false),
+            extract: None,
+            number_conditions: vec![],
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// Options for deciding what happened based on a command's exit code.
+#[derive(Debug, StructOpt)]
+pub struct ExitOpt {
+    /// Report success as soon as the command exits with status 0.
+    #[structopt(long = "until-success")]
+    until_success: bool,
+
+    /// Report success as soon as the command exits with one of these
+    /// statuses, like "3" or "1,3,10-20".
+    #[structopt(long = "until-exit", value_name = "CODES")]
+    until_exit: Option<ExitCodes>,
+
+    /// Report failure as soon as the command exits with one of these
+    /// statuses, like "3" or "1,3,10-20".
+    #[structopt(long = "fail-on-exit", value_name = "CODES")]
+    fail_on_exit: Option<ExitCodes>,
+}
+
+impl ExitOpt {
+    /// The exit conditions specified by the user.
+    pub fn conditions(&self) -> Vec<ExitCondition> {
+        let mut conditions = vec![];
+        if let Some(codes) = &self.fail_on_exit {
+            conditions.push(ExitCondition {
+                outcome: Outcome::Failure,
+                codes: codes.clone(),
+            });
+        }
+        if self.until_success {
+            conditions.push(ExitCondition {
+                outcome: Outcome::Success,
+                codes: ExitCodes(vec![0..=0]),
+            });
+        }
+        if let Some(codes) = &self.until_exit {
+            conditions.push(ExitCondition {
+                outcome: Outcome::Success,
+                codes: codes.clone(),
+            });
+        }
+        conditions
+    }
+}
+
+/// Options for checking the JSON printed by a command.
+#[derive(Debug, StructOpt)]
+pub struct JsonOpt {
+    /// Report success when the command's standard output is JSON matching
+    /// this path comparison, like '.status.phase == "Succeeded"'. Only a
+    /// path, or a path compared to a JSON value, is supported.
+    #[structopt(
+        long = "success-json",
+        value_name = "QUERY",
+        conflicts_with = "follow"
+    )]
+    success_json: Option<JsonQuery>,
+
+    /// Report failure when the command's standard output is JSON matching
+    /// this path comparison.
+    #[structopt(
+        long = "failure-json",
+        value_name = "QUERY",
+        conflicts_with = "follow"
+    )]
+    failure_json: Option<JsonQuery>,
+}
+
+impl JsonOpt {
+    /// The JSON conditions specified by the user.
+    pub fn conditions(&self) -> Vec<JsonCondition> {
+        let mut conditions = vec![];
+        if let Some(query) = &self.failure_json {
+            conditions.push(JsonCondition {
+                outcome: Outcome::Failure,
+                query: query.clone(),
+            });
+        }
+        if let Some(query) = &self.success_json {
+            conditions.push(JsonCondition {
+                outcome: Outcome::Success,
+                query: query.clone(),
+            });
+        }
+        conditions
+    }
+}
+
+/// Report `outcome` when a command prints JSON matching `query`.
+#[derive(Clone, Debug)]
+pub struct JsonCondition {
+    outcome: Outcome,
+    query: JsonQuery,
+}
+
+impl fmt::Display for JsonCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: json {}", self.outcome, self.query)
+    }
+}
+
+/// Options for checking a number extracted from a command's output.
+#[derive(Debug, StructOpt)]
+pub struct NumberOpt {
+    /// Extract a number from each run's output, using the first capture group
+    /// if there is one, or else the whole match.
+    #[structopt(long = "extract", value_name = "REGEX", conflicts_with = "follow")]
+    extract: Option<Regex>,
+
+    /// Report success when the extracted number matches a condition like
+    /// "<= 0".
+    #[structopt(
+        long = "success-when",
+        value_name = "CONDITION",
+        requires = "extract"
+    )]
+    success_when: Option<NumberCondition>,
+
+    /// Report failure when the extracted number matches a condition like
+    /// "> 1000".
+    #[structopt(
+        long = "failure-when",
+        value_name = "CONDITION",
+        requires = "extract"
+    )]
+    failure_when: Option<NumberCondition>,
+
+    /// Notify whenever the extracted number rises to this threshold or falls
+    /// back below it, and keep watching.
+    #[structopt(
+        long = "notify-crossing",
+        value_name = "THRESHOLD",
+        requires = "extract"
+    )]
+    notify_crossing: Option<f64>,
+
+    /// With `--notify-crossing`, only report falling back below the threshold
+    /// once the number is at least this far below it, so that a number which
+    /// hovers around the threshold doesn't send lots of notifications.
+    #[structopt(
+        long = "hysteresis",
+        value_name = "AMOUNT",
+        requires = "notify-crossing",
+        parse(try_from_str = parse_hysteresis)
+    )]
+    hysteresis: Option<f64>,
+}
+
+impl NumberOpt {
+    /// The regex used to extract a number, and the conditions to check it
+    /// against.
+    pub fn extractor(&self) -> Option<(Regex, Vec<NumberCondition>)> {
+        let extract = self.extract.clone()?;
+        let mut conditions = vec![];
+        if let Some(condition) = &self.failure_when {
+            conditions.push(NumberCondition {
+                outcome: Outcome::Failure,
+                ..condition.clone()
+            });
+        }
+        if let Some(condition) = &self.success_when {
+            conditions.push(condition.clone());
+        }
+        Some((extract, conditions))
+    }
+
+    /// Track when the extracted number crosses the user's threshold.
+    pub fn crossing(&self) -> Option<Crossing> {
+        Some(Crossing {
+            threshold: self.notify_crossing?,
+            hysteresis: self.hysteresis.unwrap_or(0.0),
+            above: None,
+        })
+    }
+}
+
+/// Report `outcome` when an extracted number compares to `value` using
+/// `comparison`. Parsed from strings like "<= 0".
+#[derive(Clone, Debug)]
+pub struct NumberCondition {
+    outcome: Outcome,
+    comparison: Op,
+    value: f64,
+}
+
+impl NumberCondition {
+    /// Is `number` matched by this condition?
+    fn is_match(&self, number: f64) -> bool {
+        self.comparison.compare_numbers(number, self.value)
+    }
+}
+
+impl FromStr for NumberCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<NumberCondition> {
+        let invalid = || Error::InvalidCondition {
+            condition: s.to_owned(),
+        };
+        let (comparison, value) = Op::strip_prefix(s.trim()).ok_or_else(invalid)?;
+        let value = value.trim().parse::<f64>().map_err(|_| invalid())?;
+        Ok(NumberCondition {
+            outcome: Outcome::Success,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for NumberCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: value {} {}",
+            self.outcome,
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+/// Keeps track of whether a number is above or below a threshold.
+#[derive(Clone, Debug)]
+pub struct Crossing {
+    threshold: f64,
+    hysteresis: f64,
+    /// Were we last above the threshold? `None` until we've seen a number.
+    above: Option<bool>,
+}
+
+impl Crossing {
+    /// Record the latest number. If it crossed the threshold, describe what
+    /// happened.
+    pub fn update(&mut self, number: f64) -> Option<String> {
+        let above = match self.above {
+            Some(true) => number >= self.threshold - self.hysteresis,
+            _ => number >= self.threshold,
+        };
+        let previous = self.above.replace(above);
+        match (previous, above) {
+            (Some(false), true) => {
+                Some(format!("Rose to {} (threshold {})", number, self.threshold))
+            }
+            (Some(true), false) => {
+                Some(format!("Fell to {} (threshold {})", number, self.threshold))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A set of exit codes, like "1,3,10-20".
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExitCodes(Vec<RangeInclusive<i32>>);
+
+impl ExitCodes {
+    /// Does this set contain `code`?
+    fn contains(&self, code: i32) -> bool {
+        self.0.iter().any(|range| range.contains(&code))
+    }
+}
+
+impl FromStr for ExitCodes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ExitCodes> {
+        let invalid = || Error::InvalidExitCodes {
+            value: s.to_owned(),
+        };
+        let parse = |code: &str| code.trim().parse::<i32>().map_err(|_| invalid());
+        let mut ranges = vec![];
+        for part in s.split(',') {
+            let range = match part.find('-') {
+                // A leading `-` would be a negative number, which isn't a
+                // valid exit code anyway.
+                Some(idx) if idx > 0 => {
+                    parse(&part[..idx])?..=parse(&part[idx + 1..])?
+                }
+                _ => parse(part)?..=parse(part)?,
+            };
+            if range.is_empty() || *range.start() < 0 {
+                return Err(invalid());
+            }
+            ranges.push(range);
+        }
+        Ok(ExitCodes(ranges))
+    }
+}
+
+impl fmt::Display for ExitCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self
+            .0
+            .iter()
+            .map(|range| {
+                if range.start() == range.end() {
+                    range.start().to_string()
+                } else {
+                    format!("{}-{}", range.start(), range.end())
+                }
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+/// Report `outcome` when a command exits with one of `codes`.
+#[derive(Clone, Debug)]
+pub struct ExitCondition {
+    outcome: Outcome,
+    codes: ExitCodes,
+}
+
+impl fmt::Display for ExitCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: exit {}", self.outcome, self.codes)
+    }
+}
+
+/// A pattern to look for, and what it means.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    name: String,
+    outcome: Outcome,
+    pattern: Regex,
+    /// Only check this stream. If `None`, check both.
+    stream: Option<Stream>,
+    /// A template for the notification message. This may use any capture
+    /// groups in `pattern`.
+    message: Option<Template>,
+}
+
+impl Rule {
+    fn new(name: &str, outcome: Outcome, pattern: Regex) -> Rule {
+        Rule {
+            name: name.to_owned(),
+            outcome,
+            pattern,
+            stream: None,
+            message: None,
+        }
+    }
+
+    /// Build a rule from our config file.
+    fn from_config(config: &RuleConfig) -> Result<Rule> {
+        Ok(Rule {
+            name: config.name().to_owned(),
+            outcome: config.outcome(),
+            pattern: Regex::new(config.pattern())?,
+            stream: parse_stream(config.stream().unwrap_or("both"))?,
+            message: config.message().cloned(),
+        })
+    }
+
+    /// Make sure we report an outcome that means something when text
+    /// matches. Changes and unknown exits are only reported by `alert`
+    /// itself, and watching would stop as if the command had failed.
+    fn check_outcome(&self) -> Result<()> {
+        match self.outcome {
+            Outcome::Changed | Outcome::Finished => Err(Error::InvalidRuleOutcome {
+                name: self.name.clone(),
+                outcome: self.outcome.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure our capture groups don't hide any built-in template
+    /// variables.
+    fn check_capture_names(&self) -> Result<()> {
+        let reserved = self
+            .pattern
+            .capture_names()
+            .flatten()
+            .find(|name| BUILTIN_VARIABLES.contains(name));
+        match reserved {
+            Some(name) => Err(Error::ReservedCaptureName {
+                name: name.to_owned(),
+                pattern: self.pattern.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check `line` against this rule.
+    fn check(&self, line: &Line) -> Option<Match> {
+        if self.stream.is_some_and(|stream| stream != line.stream) {
+            return None;
+        }
+        let captures = self.pattern.captures(&line.text)?;
+        // Make both named and numbered groups available to templates.
+        let mut fields = vec![];
+        for (i, name) in self.pattern.capture_names().enumerate() {
+            if let Some(value) = captures.get(i) {
+                let value = value.as_str().to_owned();
+                if let Some(name) = name {
+                    fields.push((name.to_owned(), value.clone()));
+                }
+                fields.push((i.to_string(), value));
+            }
+        }
+        Some(Match {
+            outcome: self.outcome,
+            stream: Some(line.stream),
+            rule: Some(self.name.clone()),
+            fields,
+            message: self.message.clone(),
+        })
+    }
+}
+
+/// Parse `NAME[@STREAM]=REGEX`, which reports failure unless we're told
+/// otherwise.
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rule> {
+        let invalid = || Error::InvalidRule { rule: s.to_owned() };
+        let idx = s.find('=').ok_or_else(invalid)?;
+        let (name, stream) = match s[..idx].find('@') {
+            Some(at) => (&s[..at], parse_stream(&s[at + 1..idx])?),
+            None => (&s[..idx], None),
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let mut rule = Rule::new(name, Outcome::Failure, Regex::new(&s[idx + 1..])?);
+        rule.stream = stream;
+        Ok(rule)
+    }
+}
+
+/// The rules in `rules` called `name`. It's an error if there aren't any.
+fn rules_named<'r>(rules: &'r mut [Rule], name: &str) -> Result<Vec<&'r mut Rule>> {
+    let found = rules
+        .iter_mut()
+        .filter(|rule| rule.name == name)
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        Err(Error::UnknownRule {
+            name: name.to_owned(),
+        })
+    } else {
+        Ok(found)
+    }
+}
+
+/// Parse the name of a stream to check, where "both" means `None`.
+fn parse_stream(name: &str) -> Result<Option<Stream>> {
+    match name {
+        "both" => Ok(None),
+        "stdout" => Ok(Some(Stream::Stdout)),
+        "stderr" => Ok(Some(Stream::Stderr)),
+        _ => Err(Error::UnknownStream {
+            name: name.to_owned(),
+        }),
+    }
+}
+
+/// Parse a `--hysteresis` amount, which can't be negative.
+fn parse_hysteresis(s: &str) -> Result<f64> {
+    match s.trim().parse::<f64>() {
+        Ok(amount) if amount >= 0.0 => Ok(amount),
+        _ => Err(Error::InvalidHysteresis {
+            value: s.to_owned(),
+        }),
+    }
+}
+
+/// Parse a `NAME=OUTCOME` pair for `--rule-outcome`.
+fn parse_rule_outcome(s: &str) -> Result<(String, Outcome)> {
+    match s.find('=') {
+        Some(idx) if idx > 0 => Ok((s[..idx].to_owned(), s[idx + 1..].parse()?)),
+        _ => Err(Error::InvalidRuleOutcomeSpec { spec: s.to_owned() }),
+    }
+}
+
+/// Parse a `NAME=TEMPLATE` pair for `--rule-message`.
+fn parse_rule_message(s: &str) -> Result<(String, Template)> {
+    match s.find('=') {
+        Some(idx) if idx > 0 => {
+            Ok((s[..idx].to_owned(), Template::parse(&s[idx + 1..])?))
+        }
+        _ => Err(Error::InvalidRuleMessage {
+            message: s.to_owned(),
+        }),
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == self.outcome.to_string() {
+            write!(f, "{}", self.name)?;
+        } else {
+            write!(f, "{} ({})", self.name, self.outcome)?;
+        }
+        if let Some(stream) = self.stream {
+            write!(f, " on {}", stream.name())?;
+        }
+        write!(f, ": /{}/", self.pattern)
+    }
+}
+
+/// Decides when something interesting has happened.
+#[derive(Debug)]
+pub struct Matcher {
+    /// Our rules, in the order we should check them.
+    rules: Vec<Rule>,
+    /// Conditions on the command's exit status, checked after each run.
+    exit_conditions: Vec<ExitCondition>,
+    /// Conditions on the JSON printed by the command, checked after each
+    /// run.
+    json_conditions: Vec<JsonCondition>,
+    /// Have we already warned that the command's output isn't JSON?
+    warned_not_json: Cell<bool>,
+    /// A regex for extracting a number from each run's output.
+    extract: Option<Regex>,
+    /// Conditions on the extracted number.
+    number_conditions: Vec<NumberCondition>,
+    timeout: Option<time::Duration>,
+}
+
+impl Matcher {
+    /// Also check the command's exit status using `exit_conditions`.
+    pub fn with_exit_conditions(
+        mut self,
+        exit_conditions: Vec<ExitCondition>,
+    ) -> Matcher {
+        self.exit_conditions = exit_conditions;
+        self.exit_conditions
+            .sort_by_key(|condition| condition.outcome == Outcome::Success);
+        self
+    }
+
+    /// Also check the command's JSON output using `json_conditions`.
+    pub fn with_json_conditions(
+        mut self,
+        json_conditions: Vec<JsonCondition>,
+    ) -> Matcher {
+        self.json_conditions = json_conditions;
+        self.json_conditions
+            .sort_by_key(|condition| condition.outcome == Outcome::Success);
+        self
+    }
+
+    /// Also extract a number from each run's output using `extractor`, and
+    /// check it against the accompanying conditions.
+    pub fn with_extractor(
+        mut self,
+        extractor: Option<(Regex, Vec<NumberCondition>)>,
+    ) -> Matcher {
+        if let Some((extract, mut conditions)) = extractor {
+            conditions.sort_by_key(|condition| condition.outcome == Outcome::Success);
+            self.extract = Some(extract);
+            self.number_conditions = conditions;
+        }
+        self
+    }
+
+    /// How long should we wait before giving up?
+    pub fn timeout(&self) -> Option<time::Duration> {
+        self.timeout
+    }
+
+    /// When should we give up, if we started at `start`?
+    pub fn deadline(&self, start: time::SystemTime) -> Option<time::SystemTime> {
+        self.timeout.map(|timeout| start + timeout)
+    }
+
+    /// Describe the patterns we're looking for.
+    pub fn describe(&self) -> String {
+        let mut parts = self.rules.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        parts.extend(self.exit_conditions.iter().map(|c| c.to_string()));
+        parts.extend(self.json_conditions.iter().map(|c| c.to_string()));
+        parts.extend(self.number_conditions.iter().map(|c| c.to_string()));
+        if parts.is_empty() {
+            "No patterns".to_owned()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Check a line of output against our rules, returning the first match.
+    pub fn check_line(&self, line: &Line) -> Option<Match> {
+        self.rules.iter().find_map(|rule| rule.check(line))
+    }
+
+    /// Check how a command exited against our exit conditions.
+    pub fn check_exit(&self, status: ExitStatus) -> Option<Match> {
+        let code = status.code()?;
+        let condition = self
+            .exit_conditions
+            .iter()
+            .find(|condition| condition.codes.contains(code))?;
+        let mut m = Match::new(condition.outcome);
+        m.rule = Some("exit".to_owned());
+        Some(m)
+    }
+
+    /// Check the standard output from a single run against our JSON
+    /// conditions. The output may contain more than one JSON value, and any
+    /// of them may match.
+    pub fn check_json(&self, lines: &[Line]) -> Option<Match> {
+        if self.json_conditions.is_empty() {
+            return None;
+        }
+        let stdout = lines
+            .iter()
+            .filter(|line| line.stream == Stream::Stdout)
+            .map(|line| &line.text[..])
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut values = vec![];
+        for value in serde_json::Deserializer::from_str(&stdout).into_iter::<Value>() {
+            match value {
+                Ok(value) => values.push(value),
+                Err(err) => {
+                    if !self.warned_not_json.replace(true) {
+                        warn!("Could not parse output as JSON: {}", err);
+                    }
+                    break;
+                }
+            }
+        }
+        let condition = self.json_conditions.iter().find(|condition| {
+            values.iter().any(|value| condition.query.is_match(value))
+        })?;
+        let mut m = Match::new(condition.outcome);
+        m.stream = Some(Stream::Stdout);
+        m.rule = Some("json".to_owned());
+        Some(m)
+    }
+
+    /// Extract a number from the output of a single run, from the first line
+    /// which matches our `--extract` regex.
+    pub fn extract_number(&self, lines: &[Line]) -> Option<f64> {
+        let extract = self.extract.as_ref()?;
+        lines.iter().find_map(|line| {
+            let captures = extract.captures(&line.text)?;
+            let text = captures.get(1).or_else(|| captures.get(0))?.as_str();
+            text.trim().parse::<f64>().ok()
+        })
+    }
+
+    /// Check the number extracted from a single run's output against our
+    /// numeric conditions.
+    pub fn check_number(&self, lines: &[Line]) -> Option<Match> {
+        let number = self.extract_number(lines)?;
+        let condition = self
+            .number_conditions
+            .iter()
+            .find(|condition| condition.is_match(number))?;
+        let mut m = Match::new(condition.outcome);
+        m.rule = Some("threshold".to_owned());
+        m.fields.push(("value".to_owned(), number.to_string()));
+        Some(m)
+    }
+
+    /// Check all the output from a single run, and how it exited. A failure
+    /// anywhere takes priority over a success, and otherwise the first match
+    /// wins.
+    pub fn check_run(&self, lines: &[Line], status: ExitStatus) -> Option<Match> {
+        let mut matches = lines
+            .iter()
+            .filter_map(|line| self.check_line(line))
+            .collect::<Vec<_>>();
+        matches.extend(self.check_json(lines));
+        matches.extend(self.check_number(lines));
+        matches.extend(self.check_exit(status));
+        matches
+            .iter()
+            .find(|m| m.outcome != Outcome::Success)
+            .or_else(|| matches.first())
+            .cloned()
+    }
+
+    /// Echo and check each line from `lines`, remembering them in `tail`.
+    /// Returns the first matching line, `Outcome::Timeout` if we reach
+    /// `deadline`, or `None` if we run out of lines or receive a signal.
+    pub fn wait_for_match(
+        &self,
+        lines: &Receiver<Line>,
+        deadline: Option<time::SystemTime>,
+        tail: &mut Tail,
+    ) -> Result<Option<Match>> {
+        loop {
+            if child::received_signal().is_some() {
+                return Ok(None);
+            }
+            let remaining = deadline.map(|deadline| {
+                deadline
+                    .duration_since(time::SystemTime::now())
+                    .unwrap_or_default()
+            });
+            let wait = remaining.map_or(child::SIGNAL_CHECK_INTERVAL, |remaining| {
+                remaining.min(child::SIGNAL_CHECK_INTERVAL)
+            });
+            let line = match lines.recv_timeout(wait) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout)
+                    if remaining.is_some_and(|remaining| remaining <= wait) =>
+                {
+                    return Ok(Some(Match::new(Outcome::Timeout)))
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            };
+            line.echo()?;
+            tail.push(&line.text);
+            if let Some(m) = self.check_line(&line) {
+                return Ok(Some(m));
+            }
+        }
+    }
+}
+
+/// What we decided after looking at some output.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub outcome: Outcome,
+    /// The stream containing the line which decided the outcome. This is
+    /// `None` if we gave up waiting.
+    pub stream: Option<Stream>,
+    /// The name of the rule which matched.
+    rule: Option<String>,
+    /// Capture groups from the rule's pattern.
+    fields: Vec<(String, String)>,
+    /// A template for the notification message.
+    message: Option<Template>,
+}
+
+impl Match {
+    /// An outcome which wasn't caused by any particular line.
+    fn new(outcome: Outcome) -> Match {
+        Match {
+            outcome,
+            stream: None,
+            rule: None,
+            fields: vec![],
+            message: None,
+        }
+    }
+
+    /// Add what we know about this match to `notification`.
+    pub fn annotate(&self, mut notification: Notification) -> Notification {
+        notification = notification.matched_stream(self.stream);
+        if let Some(rule) = &self.rule {
+            notification = notification.field("rule", rule.to_owned());
+        }
+        for (key, value) in &self.fields {
+            notification = notification.field(key.to_owned(), value.to_owned());
+        }
+        if let Some(message) = &self.message {
+            let message = message.render(|name| notification.variable(name));
+            notification = notification.with_message(message);
+        }
+        notification
+    }
+}
+
+#[test]
+fn rules_are_parsed() {
+    let rule = "failed=myjob.*(FAILED|ERROR)".parse::<Rule>().unwrap();
+    assert_eq!(rule.name, "failed");
+    assert_eq!(rule.outcome, Outcome::Failure);
+    assert_eq!(
+        rule.to_string(),
+        "failed (failure): /myjob.*(FAILED|ERROR)/"
+    );
+    let rule = "failure=oops".parse::<Rule>().unwrap();
+    assert_eq!(rule.to_string(), "failure: /oops/");
+    let rule = "jobs@stderr=a@b".parse::<Rule>().unwrap();
+    assert_eq!(rule.to_string(), "jobs (failure) on stderr: /a@b/");
+    assert!("=done".parse::<Rule>().is_err());
+    assert!("@stderr=done".parse::<Rule>().is_err());
+    assert!("jobs@stdin=done".parse::<Rule>().is_err());
+    assert!("done".parse::<Rule>().is_err());
+
+    let opt = MatchOpt::from_iter(&[
+        "test",
+        "--rule",
+        "ready=^ready$",
+        "--rule-outcome",
+        "ready=success",
+    ]);
+    let matcher = opt.matcher(&Config::default()).unwrap();
+    assert_eq!(matcher.describe(), "ready (success): /^ready$/");
+    for outcome in &["ready=bogus", "=success", "missing=success"] {
+        let opt = MatchOpt::from_iter_safe(&[
+            "test",
+            "--rule",
+            "ready=^ready$",
+            "--rule-outcome",
+            outcome,
+        ]);
+        assert!(opt.map_or(true, |opt| opt.matcher(&Config::default()).is_err()));
+    }
+}
+
+#[test]
+fn timeouts_are_durations() {
+    let matcher = MatchOpt::from_iter(&["test", "--timeout", "2h"])
+        .matcher(&Config::default())
+        .unwrap();
+    assert_eq!(matcher.timeout(), Some(time::Duration::from_secs(7200)));
+    let matcher = MatchOpt::from_iter(&["test", "-t", "90"])
+        .matcher(&Config::default())
+        .unwrap();
+    assert_eq!(matcher.timeout(), Some(time::Duration::from_secs(90)));
+    assert!(MatchOpt::from_iter_safe(&["test", "-t", "soon"]).is_err());
+}
+
+#[test]
+fn rule_messages_and_capture_names_are_checked() {
+    let opt = MatchOpt::from_iter(&[
+        "test",
+        "--rule",
+        "job=(?P<job>\\w+) failed",
+        "--rule-message",
+        "job={job} broke",
+    ]);
+    let matcher = opt.matcher(&Config::default()).unwrap();
+    let line = Line {
+        stream: Stream::Stdout,
+        text: "backup failed".to_owned(),
+    };
+    let m = matcher.check_line(&line).unwrap();
+    let notification = m.annotate(Notification::new(Outcome::Failure));
+    assert_eq!(notification.message(&Default::default()), "backup broke");
+
+    let opt = MatchOpt::from_iter(&["test", "--rule-message", "missing=oops"]);
+    assert!(opt.matcher(&Config::default()).is_err());
+    let opt = MatchOpt::from_iter(&["test", "-f", "(?P<command>\\w+) failed"]);
+    assert!(opt.matcher(&Config::default()).is_err());
+}
+
+#[test]
+fn rules_only_report_matching_outcomes() {
+    for outcome in &["changed", "finished", "error"] {
+        let opt = MatchOpt::from_iter(&[
+            "test",
+            "--rule",
+            "oom=out of memory",
+            "--rule-outcome",
+            &format!("oom={}", outcome),
+        ]);
+        let matcher = opt.matcher(&Config::default());
+        assert_eq!(matcher.is_ok(), *outcome == "error", "{}", outcome);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn failures_take_priority() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let opt = MatchOpt::from_iter(&["test", "-s", "done", "--rule", "failure=oops"]);
+    let matcher = opt.matcher(&Config::default()).unwrap();
+    let line = |text: &str| Line {
+        stream: Stream::Stdout,
+        text: text.to_owned(),
+    };
+    let lines = vec![line("done"), line("oops, done")];
+    let m = matcher.check_run(&lines, ExitStatus::from_raw(0)).unwrap();
+    assert_eq!(m.outcome, Outcome::Failure);
+    assert_eq!(m.rule.as_deref(), Some("failure"));
+}
+
+#[test]
+fn exit_codes_are_parsed() {
+    let codes = "1,3,10-20".parse::<ExitCodes>().unwrap();
+    assert_eq!(codes.to_string(), "1,3,10-20");
+    assert!(codes.contains(3) && codes.contains(15) && !codes.contains(2));
+    assert!("".parse::<ExitCodes>().is_err());
+    assert!("5-1".parse::<ExitCodes>().is_err());
+    assert!("-1".parse::<ExitCodes>().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_conditions_are_checked() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let opt =
+        ExitOpt::from_iter(&["test", "--until-success", "--fail-on-exit", "2-3"]);
+    let matcher = MatchOpt::from_iter(&["test"])
+        .matcher(&Config::default())
+        .unwrap()
+        .with_exit_conditions(opt.conditions());
+    let exit = |code: i32| ExitStatus::from_raw(code << 8);
+    assert_eq!(
+        matcher.check_exit(exit(0)).unwrap().outcome,
+        Outcome::Success
+    );
+    assert_eq!(
+        matcher.check_exit(exit(3)).unwrap().outcome,
+        Outcome::Failure
+    );
+    assert!(matcher.check_exit(exit(1)).is_none());
+}
+
+#[test]
+fn numbers_are_extracted_and_checked() {
+    let opt = NumberOpt::from_iter(&[
+        "test",
+        "--extract",
+        r"pending: (\d+)",
+        "--success-when",
+        "<= 0",
+        "--failure-when",
+        "> 1000",
+    ]);
+    let matcher = MatchOpt::from_iter(&["test"])
+        .matcher(&Config::default())
+        .unwrap()
+        .with_extractor(opt.extractor());
+    let lines = |text: &str| {
+        vec![Line {
+            stream: Stream::Stdout,
+            text: text.to_owned(),
+        }]
+    };
+    assert_eq!(
+        matcher.extract_number(&lines("pending: 12 jobs")),
+        Some(12.0)
+    );
+    assert!(matcher.check_number(&lines("pending: 12")).is_none());
+    let m = matcher.check_number(&lines("pending: 0")).unwrap();
+    assert_eq!(m.outcome, Outcome::Success);
+    let m = matcher.check_number(&lines("pending: 1001")).unwrap();
+    assert_eq!(m.outcome, Outcome::Failure);
+    assert!("=> 3".parse::<NumberCondition>().is_err());
+    assert!("<= lots".parse::<NumberCondition>().is_err());
+}
+
+#[test]
+fn crossings_use_hysteresis() {
+    let mut crossing = Crossing {
+        threshold: 100.0,
+        hysteresis: 10.0,
+        above: None,
+    };
+    assert!(crossing.update(50.0).is_none());
+    assert_eq!(
+        crossing.update(100.0).as_deref(),
+        Some("Rose to 100 (threshold 100)")
+    );
+    assert!(crossing.update(95.0).is_none());
+    assert_eq!(
+        crossing.update(89.0).as_deref(),
+        Some("Fell to 89 (threshold 100)")
+    );
+    assert!(crossing.update(99.0).is_none());
+}
+
+#[test]
+fn hysteresis_is_checked() {
+    let args = ["test", "--extract", "(\\d+)", "--notify-crossing", "5"];
+    let opt = NumberOpt::from_iter(args.iter().chain(&["--hysteresis", "1.5"]));
+    assert_eq!(opt.crossing().unwrap().hysteresis, 1.5);
+    assert!(
+        NumberOpt::from_iter_safe(args.iter().chain(&["--hysteresis", "-1"])).is_err()
+    );
+    assert!(NumberOpt::from_iter_safe(&["test", "--hysteresis", "1"]).is_err());
+}
@@ -4,20 +4,42 @@
 
 use colored::*;
 
-use super::{Notification, Notifier, Outcome};
+use super::{Notification, Notifier, Outcome, Templates};
 use crate::errors::*;
 
 /// Notify the user of an event using the console.
-pub struct ConsoleNotifier;
+pub struct ConsoleNotifier {
+    /// Templates for our titles and messages.
+    templates: Templates,
+}
+
+impl ConsoleNotifier {
+    /// Create a new notifier.
+    pub fn new(templates: Templates) -> ConsoleNotifier {
+        ConsoleNotifier { templates }
+    }
+}
 
 impl Notifier for ConsoleNotifier {
     fn send(&self, notification: &Notification) -> Result<()> {
-        let label = format!("{}:", notification.title());
-        let color_label = match notification.outcome() {
-            Outcome::Success => label.green().bold(),
-            Outcome::Failure | Outcome::Timeout => label.red().bold(),
-        };
-        eprintln!("{} {}", color_label, notification.message().bold());
+        let label = format!("{}:", notification.title(&self.templates));
+        let color_label = label.color(color(notification.outcome())).bold();
+        eprintln!(
+            "{} {}",
+            color_label,
+            notification.message(&self.templates).bold()
+        );
         Ok(())
     }
 }
+
+/// The color we use for each outcome. Other notifiers may use these as hints.
+pub fn color(outcome: Outcome) -> Color {
+    match outcome {
+        Outcome::Success => Color::Green,
+        Outcome::Interrupted => Color::Yellow,
+        Outcome::Changed => Color::Cyan,
+        Outcome::Finished => Color::Blue,
+        Outcome::Failure | Outcome::Timeout | Outcome::Error => Color::Red,
+    }
+}
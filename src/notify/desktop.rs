@@ -1,21 +1,467 @@
 //! Display ordinary desktop notifications using standard OS features.
 
+use colored::Color;
 use log::debug;
+use notify_rust::{NotificationHint, NotificationUrgency, Timeout};
+use std::cell::RefCell;
+use std::time::Duration;
 
-use super::{Notification, Notifier};
+use super::{console, Notification, Notifier, Outcome, Priority, Progress, Templates};
+use crate::config::NotifierConfig;
 use crate::errors::*;
 
+/// How long should successful notifications stay on screen, in milliseconds?
+const DEFAULT_SUCCESS_TIMEOUT: u32 = 5000;
+
+/// How long should we wait for the user to click on an action, in seconds?
+const DEFAULT_ACTION_TIMEOUT: u64 = 10;
+
 /// Display notifications on the local machine using its GUI
-pub struct DesktopNotifier;
+pub struct DesktopNotifier {
+    /// The application name to report to the notification server.
+    appname: String,
+    /// How long successful notifications should stay on screen, in
+    /// milliseconds.
+    success_timeout: u32,
+    /// Should we offer actions like "Re-run" on failure notifications? This
+    /// is off by default, because we need to wait for the user to choose one.
+    actions: bool,
+    /// How long should we wait for the user to choose an action?
+    action_timeout: Duration,
+    /// Should we show progress notifications?
+    show_progress: bool,
+    /// Our progress notification, so that we can replace it, or close it if
+    /// we never do.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    progress: RefCell<Option<notify_rust::NotificationHandle>>,
+    /// Options for this notifier, used to look up per-outcome settings.
+    config: NotifierConfig,
+    /// Templates for our titles and messages.
+    templates: Templates,
+}
+
+impl DesktopNotifier {
+    /// Create a new notifier.
+    pub fn new(
+        config: &NotifierConfig,
+        templates: Templates,
+    ) -> Result<DesktopNotifier> {
+        Ok(DesktopNotifier {
+            appname: config.option("appname").unwrap_or("alert").to_owned(),
+            success_timeout: config
+                .parse_option("success_timeout")?
+                .unwrap_or(DEFAULT_SUCCESS_TIMEOUT),
+            actions: config.parse_option("actions")?.unwrap_or(false),
+            action_timeout: Duration::from_secs(
+                config
+                    .parse_option("action_timeout")?
+                    .unwrap_or(DEFAULT_ACTION_TIMEOUT),
+            ),
+            show_progress: config.parse_option("progress")?.unwrap_or(true),
+            #[cfg(all(unix, not(target_os = "macos")))]
+            progress: RefCell::new(None),
+            config: config.to_owned(),
+            templates,
+        })
+    }
+
+    /// The themed icon to display for `outcome`.
+    fn icon(&self, outcome: Outcome) -> &str {
+        if let Some(icon) = self.config.option(&format!("{}_icon", outcome)) {
+            return icon;
+        }
+        match outcome {
+            Outcome::Success | Outcome::Changed | Outcome::Finished => {
+                "dialog-information"
+            }
+            Outcome::Failure | Outcome::Error => "dialog-error",
+            Outcome::Timeout => "dialog-warning",
+            Outcome::Interrupted => "process-stop",
+        }
+    }
+
+    /// The themed sound to play for `outcome`.
+    fn sound(&self, outcome: Outcome) -> &str {
+        if let Some(sound) = self.config.option(&format!("{}_sound", outcome)) {
+            return sound;
+        }
+        match outcome {
+            Outcome::Success => "complete",
+            Outcome::Failure | Outcome::Error => "dialog-error",
+            Outcome::Timeout | Outcome::Interrupted => "dialog-warning",
+            Outcome::Changed => "message-new-instant",
+            Outcome::Finished => "message",
+        }
+    }
+
+    /// How long should a notification for `outcome` stay on screen? Failures
+    /// stay until they're dismissed, but successes, interruptions (which the
+    /// user probably knows about), changes and other news fade away.
+    fn timeout(&self, outcome: Outcome) -> Timeout {
+        match outcome {
+            Outcome::Success
+            | Outcome::Interrupted
+            | Outcome::Changed
+            | Outcome::Finished => Timeout::Milliseconds(self.success_timeout),
+            Outcome::Failure | Outcome::Timeout | Outcome::Error => Timeout::Never,
+        }
+    }
+
+    /// Which actions should we offer for `notification`? We only offer
+    /// actions for failures, because we need to wait around for the user to
+    /// click on them.
+    fn actions(
+        &self,
+        notification: &Notification,
+    ) -> Vec<(&'static str, &'static str)> {
+        let mut actions = vec![];
+        if !self.actions
+            || notification.outcome() == Outcome::Success
+            || notification.outcome() == Outcome::Changed
+            || notification.outcome() == Outcome::Finished
+            || !actions::server_supports_actions()
+        {
+            return actions;
+        }
+        // If we couldn't run the command, running it again won't help.
+        if notification.command().is_some() && notification.outcome() != Outcome::Error
+        {
+            actions.push(("rerun", "Re-run"));
+        }
+        if notification.log_file().is_some()
+            || notification.variable("output_tail").is_some()
+        {
+            actions.push(("open-log", "Open log"));
+        }
+        if notification.command().is_some() {
+            actions.push(("copy-command", "Copy command"));
+        }
+        actions
+    }
+}
 
 impl Notifier for DesktopNotifier {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn validate(&self) -> Result<()> {
+        // Make sure somebody is listening for notifications.
+        let info = notify_rust::get_server_information()?;
+        debug!("Desktop notification server: {:?}", info);
+        Ok(())
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn progress(&self, progress: &Progress) -> Result<()> {
+        if !self.show_progress {
+            return Ok(());
+        }
+        let mut desktop_notification = notify_rust::Notification::new();
+        desktop_notification
+            .appname(&self.appname)
+            .summary(&progress.command.to_string())
+            .body(&progress.message())
+            .icon("view-refresh")
+            .urgency(NotificationUrgency::Low)
+            .timeout(Timeout::Never)
+            .hint(NotificationHint::Category("x-alert.progress".to_owned()))
+            .hint(NotificationHint::SuppressSound(true));
+        if let Some(progress) = &*self.progress.borrow() {
+            desktop_notification.id(progress.id());
+        }
+        debug!("Updating desktop progress notification");
+        let handle = desktop_notification.show()?;
+        *self.progress.borrow_mut() = Some(handle);
+        Ok(())
+    }
+
     fn send(&self, notification: &Notification) -> Result<()> {
+        let outcome = notification.outcome();
+        let mut desktop_notification = notify_rust::Notification::new();
+        desktop_notification
+            .appname(&self.appname)
+            .summary(&notification.title(&self.templates))
+            .body(&notification.message(&self.templates))
+            .icon(self.icon(outcome))
+            .urgency(urgency(notification.priority()))
+            .timeout(self.timeout(outcome))
+            .hint(NotificationHint::Category(format!("x-alert.{}", outcome)))
+            .hint(NotificationHint::SoundName(self.sound(outcome).to_owned()))
+            // Frame color, as supported by `dunst` and some other servers.
+            .hint(NotificationHint::Custom(
+                "frcolor".to_owned(),
+                hex_color(console::color(outcome)).to_owned(),
+            ));
+        // Replace our progress notification, if we have one.
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            if let Some(progress) = self.progress.borrow_mut().take() {
+                desktop_notification.id(progress.id());
+            }
+        }
+        let actions = self.actions(notification);
+        for (id, label) in &actions {
+            desktop_notification.action(id, label);
+        }
+
+        // Start listening for actions before we show the notification, so
+        // that we don't miss a quick click.
+        #[cfg(all(unix, not(target_os = "macos")))]
+        let listener = if actions.is_empty() {
+            None
+        } else {
+            Some(actions::ActionListener::new()?)
+        };
+
         debug!("Sending notification to desktop");
-        notify_rust::Notification::new()
-            .summary(&notification.title())
-            .body(&notification.message())
-            .show()?;
+        let _handle = desktop_notification.show()?;
         debug!("Sent notification to desktop");
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            if let Some(listener) = listener {
+                listener.wait_for_action(
+                    _handle.id(),
+                    self.action_timeout,
+                    notification,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Drop for DesktopNotifier {
+    fn drop(&mut self) {
+        // If we never replaced our progress notification, because we stopped
+        // early or the final outcome was filtered out, don't leave it on
+        // screen forever.
+        if let Some(progress) = self.progress.get_mut().take() {
+            debug!("Closing desktop progress notification");
+            progress.close();
+        }
+    }
+}
+
+/// Convert a `Priority` to a desktop notification urgency.
+fn urgency(priority: Priority) -> NotificationUrgency {
+    match priority {
+        Priority::Lowest | Priority::Low => NotificationUrgency::Low,
+        Priority::Normal => NotificationUrgency::Normal,
+        Priority::High | Priority::Emergency => NotificationUrgency::Critical,
+    }
+}
+
+/// Convert a terminal color to an HTML-style hex color.
+fn hex_color(color: Color) -> &'static str {
+    match color {
+        Color::Black | Color::BrightBlack => "#000000",
+        Color::Red | Color::BrightRed => "#cc0000",
+        Color::Green | Color::BrightGreen => "#4e9a06",
+        Color::Yellow | Color::BrightYellow => "#c4a000",
+        Color::Blue | Color::BrightBlue => "#3465a4",
+        Color::Magenta | Color::BrightMagenta => "#75507b",
+        Color::Cyan | Color::BrightCyan => "#06989a",
+        Color::White | Color::BrightWhite => "#d3d7cf",
+    }
+}
+
+/// Support for notification actions, which relies on D-Bus signals.
+#[cfg(all(unix, not(target_os = "macos")))]
+mod actions {
+    use dbus::{BusType, Connection, MessageType};
+    use log::{debug, warn};
+    use std::env;
+    use std::fs;
+    use std::io::{self, prelude::*};
+    use std::path::PathBuf;
+    use std::process::{self, Stdio};
+    use std::time::{Duration, Instant};
+
+    use crate::command::Command;
+    use crate::errors::*;
+    use crate::notify::Notification;
+    use crate::util::cache_dir;
+
+    /// The D-Bus interface used by the notification server.
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+
+    /// Programs which can copy standard input to the clipboard, in order of
+    /// preference.
+    const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+    ];
+
+    /// Does the notification server support actions?
+    pub fn server_supports_actions() -> bool {
+        notify_rust::get_capabilities()
+            .map(|caps| caps.iter().any(|c| c == "actions"))
+            .unwrap_or(false)
+    }
+
+    /// Listens for the user choosing a notification action. The
+    /// notification server only sends us signals which we've subscribed to,
+    /// so create this before showing the notification.
+    pub struct ActionListener {
+        connection: Connection,
+    }
+
+    impl ActionListener {
+        /// Subscribe to action signals.
+        pub fn new() -> Result<ActionListener> {
+            let connection = Connection::get_private(BusType::Session)
+                .map_err(notify_rust::Error::from)?;
+            for member in &["ActionInvoked", "NotificationClosed"] {
+                let rule = format!("interface='{}',member='{}'", INTERFACE, member);
+                connection
+                    .add_match(&rule)
+                    .map_err(notify_rust::Error::from)?;
+            }
+            Ok(ActionListener { connection })
+        }
+
+        /// Wait up to `timeout` for the user to choose an action on
+        /// notification `id`, and run it.
+        pub fn wait_for_action(
+            &self,
+            id: u32,
+            timeout: Duration,
+            notification: &Notification,
+        ) -> Result<()> {
+            match self.next_action(id, timeout) {
+                Some(action) => run_action(&action, notification),
+                None => {
+                    debug!("No desktop notification action was chosen");
+                    Ok(())
+                }
+            }
+        }
+
+        /// Wait for an action on notification `id`. Returns `None` if the
+        /// notification is closed or we time out.
+        fn next_action(&self, id: u32, timeout: Duration) -> Option<String> {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining == Duration::from_secs(0) {
+                    return None;
+                }
+                let timeout_ms = remaining.as_millis().max(1) as u32;
+                for msg in self.connection.incoming(timeout_ms) {
+                    if msg.msg_type() != MessageType::Signal
+                        || msg.interface().as_deref() != Some(INTERFACE)
+                    {
+                        continue;
+                    }
+                    match msg.member().as_deref() {
+                        Some("ActionInvoked") => {
+                            if let Ok((nid, action)) = msg.read2::<u32, &str>() {
+                                if nid == id {
+                                    return Some(action.to_owned());
+                                }
+                            }
+                        }
+                        Some("NotificationClosed") if msg.read1::<u32>() == Ok(id) => {
+                            return None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the action that the user chose.
+    fn run_action(action: &str, notification: &Notification) -> Result<()> {
+        debug!("Running desktop notification action {:?}", action);
+        match (action, notification.command(), notification.log_file()) {
+            ("rerun", Some(cmd), _) => {
+                // Re-run the command using `alert run`, so that we hear about
+                // it.
+                let alert = env::current_exe()
+                    .map(|exe| exe.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| "alert".to_owned());
+                let mut args = vec![alert, "run".to_owned(), cmd.cmd.clone()];
+                args.extend(cmd.args.iter().cloned());
+                spawn(&Command::from_slice(&args)?)
+            }
+            ("open-log", _, Some(log_file)) => open(log_file.to_owned()),
+            ("open-log", _, None) => match notification.variable("output_tail") {
+                // We don't keep a log, so save the output we captured.
+                Some(output) => {
+                    let dir = cache_dir().unwrap_or_else(env::temp_dir);
+                    let path = dir.join("last-output.log");
+                    fs::create_dir_all(&dir)
+                        .and_then(|()| fs::write(&path, output))
+                        .map_err(|source| Error::CouldNotWriteOutput {
+                            path: path.clone(),
+                            source,
+                        })?;
+                    open(path)
+                }
+                None => Ok(()),
+            },
+            ("copy-command", Some(cmd), _) => copy_to_clipboard(&cmd.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Open `path` using the desktop's default application.
+    fn open(path: PathBuf) -> Result<()> {
+        let path = path.to_string_lossy().into_owned();
+        spawn(&Command::from_slice(&["xdg-open".to_owned(), path])?)
+    }
+
+    /// Start running `cmd` in the background.
+    fn spawn(cmd: &Command) -> Result<()> {
+        process::Command::new(&cmd.cmd)
+            .args(&cmd.args)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|source| Error::CouldNotRun {
+                cmd: cmd.to_owned(),
+                source,
+            })?;
+        Ok(())
+    }
+
+    /// Copy `text` to the clipboard using the first clipboard program we
+    /// find.
+    fn copy_to_clipboard(text: &str) -> Result<()> {
+        for &args in CLIPBOARD_COMMANDS {
+            let result = process::Command::new(args[0])
+                .args(&args[1..])
+                .stdin(Stdio::piped())
+                .spawn();
+            let mut child = match result {
+                Ok(child) => child,
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(source) => {
+                    return Err(Error::CouldNotRun {
+                        cmd: Command::from_slice(&[args[0].to_owned()])?,
+                        source,
+                    })
+                }
+            };
+            if let Some(mut stdin) = child.stdin.take() {
+                if let Err(err) = stdin.write_all(text.as_bytes()) {
+                    warn!("Could not copy to clipboard using {}: {}", args[0], err);
+                }
+            }
+            let _ = child.wait();
+            return Ok(());
+        }
+        warn!("Could not find a program to copy to the clipboard");
         Ok(())
     }
 }
+
+/// Notification actions aren't supported on this platform.
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod actions {
+    /// Does the notification server support actions?
+    pub fn server_supports_actions() -> bool {
+        false
+    }
+}
@@ -1,21 +1,58 @@
 //! Various tools for notifying the user.
 
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 mod console;
 #[cfg(feature = "notify-rust")]
 mod desktop;
 mod notifyapp;
+mod opt;
 mod pushover;
+mod template;
 
+pub use self::opt::NotifyOpt;
+pub use self::template::{Template, Templates};
+
+use crate::child::signal_name;
 use crate::command::Command;
+use crate::config::Config;
 use crate::errors::*;
+use crate::output::Stream;
+use crate::util::{format_duration, hostname};
 
 /// A notification we want to send to the user.
 #[derive(Clone, Debug)]
 pub struct Notification {
     outcome: Outcome,
+    priority: Priority,
+    title: Option<String>,
+    message: Option<String>,
     command: Option<Command>,
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    error: Option<String>,
+    duration: Option<Duration>,
+    runs: Option<u64>,
+    output_tail: Option<String>,
+    diff: Option<String>,
+    crossing: Option<String>,
+    matched_stream: Option<Stream>,
+    fields: BTreeMap<String, String>,
+    finished_at: SystemTime,
+    url: Option<String>,
+    url_title: Option<String>,
+    attachment: Option<PathBuf>,
+    log_file: Option<PathBuf>,
+    /// What we're reporting on, for use in default titles.
+    subject: &'static str,
 }
 
 impl Notification {
@@ -23,17 +60,153 @@ impl Notification {
     pub fn new(outcome: Outcome) -> Notification {
         Notification {
             outcome,
+            priority: outcome.default_priority(),
+            title: None,
+            message: None,
             command: None,
+            exit_code: None,
+            signal: None,
+            error: None,
+            duration: None,
+            runs: None,
+            output_tail: None,
+            diff: None,
+            crossing: None,
+            matched_stream: None,
+            fields: BTreeMap::new(),
+            finished_at: SystemTime::now(),
+            url: None,
+            url_title: None,
+            attachment: None,
+            log_file: None,
+            subject: "Command",
         }
     }
 
     /// Specify the command for a newly-created notification, using
     /// the builder pattern.
-    pub fn command(mut self, command: Command) -> Notification {
+    pub fn with_command(mut self, command: Command) -> Notification {
         self.command = Some(command);
         self
     }
 
+    /// Override the default priority of this notification.
+    pub fn with_priority(mut self, priority: Priority) -> Notification {
+        self.priority = priority;
+        self
+    }
+
+    /// Specify a title for this notification, overriding any templates.
+    pub fn with_title(mut self, title: String) -> Notification {
+        self.title = Some(title);
+        self
+    }
+
+    /// Specify a message body for this notification, overriding any
+    /// templates.
+    pub fn with_message(mut self, message: String) -> Notification {
+        self.message = Some(message);
+        self
+    }
+
+    /// Specify the exit code of the command, if it has one.
+    pub fn exit_code(mut self, exit_code: Option<i32>) -> Notification {
+        self.exit_code = exit_code;
+        self
+    }
+
+    /// Specify the signal which stopped the command, if any.
+    pub fn signal(mut self, signal: Option<i32>) -> Notification {
+        self.signal = signal;
+        self
+    }
+
+    /// Describe the error that prevented us from running the command.
+    pub fn with_error(mut self, error: String) -> Notification {
+        self.error = Some(error);
+        self
+    }
+
+    /// Specify how long we spent running the command.
+    pub fn duration(mut self, duration: Duration) -> Notification {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Specify how many times we ran the command before we gave up.
+    pub fn runs(mut self, runs: u64) -> Notification {
+        self.runs = Some(runs);
+        self
+    }
+
+    /// Specify the last few lines of output from the command.
+    pub fn output_tail(mut self, output_tail: String) -> Notification {
+        self.output_tail = Some(output_tail);
+        self
+    }
+
+    /// Describe how the output of the command changed.
+    pub fn diff(mut self, diff: String) -> Notification {
+        self.diff = Some(diff);
+        self
+    }
+
+    /// Describe how a number extracted from the output crossed the user's
+    /// threshold.
+    pub fn crossing(mut self, crossing: String) -> Notification {
+        self.crossing = Some(crossing);
+        self
+    }
+
+    /// Specify which output stream contained the line that decided the
+    /// outcome, if any.
+    pub fn matched_stream(mut self, stream: Option<Stream>) -> Notification {
+        self.matched_stream = stream;
+        self
+    }
+
+    /// Add a custom field which can be used by templates.
+    pub fn field<K, V>(mut self, key: K, value: V) -> Notification
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    /// Specify a URL with more information about this notification.
+    pub fn with_url(mut self, url: String) -> Notification {
+        self.url = Some(url);
+        self
+    }
+
+    /// Specify a title for our URL.
+    pub fn with_url_title(mut self, url_title: String) -> Notification {
+        self.url_title = Some(url_title);
+        self
+    }
+
+    /// Attach a file (typically an image) to this notification.
+    pub fn with_attachment(mut self, attachment: PathBuf) -> Notification {
+        self.attachment = Some(attachment);
+        self
+    }
+
+    /// Specify a log file written by the command, which notifiers may offer
+    /// to open.
+    pub fn with_log_file(mut self, log_file: PathBuf) -> Notification {
+        self.log_file = Some(log_file);
+        self
+    }
+
+    /// Describe what we're reporting on in default titles, if it isn't a
+    /// command, using the builder pattern.
+    pub fn with_subject(mut self, subject: &'static str) -> Notification {
+        self.subject = subject;
+        self
+    }
+
     /// Get the outcome associated with this notification, in case a
     /// particular notifier wishes to further customize message options
     /// like sounds or colors.
@@ -41,27 +214,156 @@ impl Notification {
         self.outcome
     }
 
+    /// Get the priority of this notification, which each notifier should
+    /// translate into the closest native equivalent.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// The command we ran, if any.
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    /// When did the event we're reporting happen?
+    pub fn finished_at(&self) -> SystemTime {
+        self.finished_at
+    }
+
+    /// A URL with more information about this notification.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// The title to display for `url`.
+    pub fn url_title(&self) -> Option<&str> {
+        self.url_title.as_deref()
+    }
+
+    /// A file attached to this notification.
+    pub fn attachment(&self) -> Option<&Path> {
+        self.attachment.as_deref()
+    }
+
+    /// A log file written by the command.
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
     /// Generate a title for this notification.
-    pub fn title(&self) -> String {
+    pub fn title(&self, templates: &Templates) -> String {
+        if let Some(ref title) = self.title {
+            return title.to_owned();
+        }
+        if let Some(template) = templates.title(self.outcome) {
+            return template.render(|name| self.variable(name));
+        }
+        let subject = self.subject;
         match self.outcome {
-            Outcome::Success => "Command succeeded".to_owned(),
-            Outcome::Failure => "Command failed".to_owned(),
-            Outcome::Timeout => "Command timed out".to_owned(),
+            Outcome::Success => format!("{} succeeded", subject),
+            Outcome::Failure => format!("{} failed", subject),
+            Outcome::Timeout => format!("{} timed out", subject),
+            Outcome::Interrupted => format!("{} interrupted", subject),
+            Outcome::Error if subject == "Command" => {
+                "Could not run command".to_owned()
+            }
+            Outcome::Error => format!("{} error", subject),
+            Outcome::Changed if self.crossing.is_some() => {
+                format!("{} crossed threshold", subject)
+            }
+            Outcome::Changed => format!("{} output changed", subject),
+            Outcome::Finished => format!("{} finished", subject),
         }
     }
 
     /// Generate a message body for this notification.
-    pub fn message(&self) -> String {
+    pub fn message(&self, templates: &Templates) -> String {
+        if let Some(ref message) = self.message {
+            return message.to_owned();
+        }
+        if let Some(template) = templates.message(self.outcome) {
+            return template.render(|name| self.variable(name));
+        }
+
         let mut lines = vec![];
         if let Some(ref command) = self.command {
             lines.push(format!("{}", command));
+        } else if let Some(ref log_file) = self.log_file {
+            lines.push(format!("{}", log_file.display()));
+        }
+        if let Some(ref error) = self.error {
+            lines.push(error.to_owned());
+        }
+        if let Some(ref diff) = self.diff {
+            lines.push(diff.to_owned());
+        }
+        if let Some(ref crossing) = self.crossing {
+            lines.push(crossing.to_owned());
+        }
+        match (self.outcome, self.signal) {
+            (Outcome::Interrupted, Some(signal)) => {
+                lines.push(format!("Interrupted by {}", signal_name(signal)));
+            }
+            (_, Some(signal)) => {
+                lines.push(format!("Killed by {}", signal_name(signal)));
+            }
+            (_, None) => {}
+        }
+        if let (Outcome::Timeout, Some(duration)) = (self.outcome, self.duration) {
+            lines.push(format!("Gave up after {}", format_duration(duration)));
+        }
+        if let (Outcome::Timeout, Some(runs)) = (self.outcome, self.runs) {
+            lines.push(format!("Stopped after {} runs", runs));
         }
         lines.join("\n")
     }
+
+    /// Look up the value of a template placeholder. These names take
+    /// priority over custom fields, so keep `BUILTIN_VARIABLES` up to date.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "outcome" => Some(self.outcome.to_string()),
+            "priority" => Some(self.priority.to_string()),
+            "command" => self.command.as_ref().map(|c| c.to_string()),
+            "exit_code" => self.exit_code.map(|c| c.to_string()),
+            "signal" => self.signal.map(signal_name),
+            "error" => self.error.clone(),
+            "duration" => self.duration.map(format_duration),
+            "runs" => self.runs.map(|runs| runs.to_string()),
+            "host" => hostname(),
+            "output_tail" => self.output_tail.clone(),
+            "diff" => self.diff.clone(),
+            "crossing" => self.crossing.clone(),
+            "stream" => self.matched_stream.map(|s| s.name().to_owned()),
+            "log_file" => self.log_file.as_ref().map(|p| p.display().to_string()),
+            _ => self.fields.get(name).cloned(),
+        }
+    }
 }
 
+/// Template variables which we fill in ourselves, and which can't be
+/// overridden by custom fields or capture groups.
+pub const BUILTIN_VARIABLES: &[&str] = &[
+    "outcome",
+    "priority",
+    "command",
+    "exit_code",
+    "signal",
+    "error",
+    "duration",
+    "runs",
+    "host",
+    "output_tail",
+    "diff",
+    "crossing",
+    "stream",
+    "log_file",
+    "rule",
+];
+
 /// What happened to the process we were running?
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum Outcome {
     /// The process succeeded.
     Success,
@@ -69,9 +371,30 @@ pub enum Outcome {
     Failure,
     /// We timed out waiting for something to happen.
     Timeout,
+    /// The process was cancelled by the user or by a signal.
+    Interrupted,
+    /// We couldn't run the process at all.
+    Error,
+    /// The output of a command we're watching changed.
+    Changed,
+    /// The process exited, but we don't know whether it succeeded.
+    Finished,
 }
 
 impl Outcome {
+    /// Every possible outcome.
+    pub fn all() -> &'static [Outcome] {
+        &[
+            Outcome::Success,
+            Outcome::Failure,
+            Outcome::Timeout,
+            Outcome::Interrupted,
+            Outcome::Error,
+            Outcome::Changed,
+            Outcome::Finished,
+        ]
+    }
+
     /// Create an `Outcome` from a boolean value indicating whether our
     /// process succeeded.
     pub fn from_bool(success: bool) -> Outcome {
@@ -81,10 +404,149 @@ impl Outcome {
             Outcome::Failure
         }
     }
+
+    /// The priority we use for this outcome unless the user asks for
+    /// something else. Failures should be harder to miss than successes.
+    pub fn default_priority(self) -> Priority {
+        match self {
+            Outcome::Success
+            | Outcome::Interrupted
+            | Outcome::Changed
+            | Outcome::Finished => Priority::Normal,
+            Outcome::Failure | Outcome::Timeout | Outcome::Error => Priority::High,
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Outcome> {
+        match s {
+            "success" => Ok(Outcome::Success),
+            "failure" => Ok(Outcome::Failure),
+            "timeout" => Ok(Outcome::Timeout),
+            "interrupted" => Ok(Outcome::Interrupted),
+            "error" => Ok(Outcome::Error),
+            "changed" => Ok(Outcome::Changed),
+            "finished" => Ok(Outcome::Finished),
+            _ => Err(Error::UnknownOutcome { name: s.to_owned() }),
+        }
+    }
+}
+
+impl TryFrom<String> for Outcome {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Outcome> {
+        s.parse()
+    }
+}
+
+impl From<Outcome> for String {
+    fn from(outcome: Outcome) -> String {
+        outcome.to_string()
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success => "success".fmt(f),
+            Outcome::Failure => "failure".fmt(f),
+            Outcome::Timeout => "timeout".fmt(f),
+            Outcome::Interrupted => "interrupted".fmt(f),
+            Outcome::Error => "error".fmt(f),
+            Outcome::Changed => "changed".fmt(f),
+            Outcome::Finished => "finished".fmt(f),
+        }
+    }
+}
+
+/// An update on a command that we're still watching.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The command we're watching.
+    pub command: Command,
+    /// How many times we've run it.
+    pub runs: u64,
+    /// How long we've been watching it.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Describe our progress for humans.
+    pub fn message(&self) -> String {
+        format!(
+            "Still waiting, {} {}, {} elapsed",
+            self.runs,
+            if self.runs == 1 { "poll" } else { "polls" },
+            format_duration(self.elapsed),
+        )
+    }
+}
+
+/// How insistently should we notify the user?
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Don't bother the user at all, if the backend supports that.
+    Lowest,
+    /// Deliver quietly.
+    Low,
+    /// An ordinary notification.
+    Normal,
+    /// Bypass quiet hours or stay on screen, if the backend supports that.
+    High,
+    /// Keep bothering the user until they respond, if the backend supports
+    /// that.
+    Emergency,
+}
+
+impl FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Priority> {
+        match s {
+            "lowest" => Ok(Priority::Lowest),
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            "emergency" => Ok(Priority::Emergency),
+            _ => Err(Error::UnknownPriority { name: s.to_owned() }),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Lowest => "lowest".fmt(f),
+            Priority::Low => "low".fmt(f),
+            Priority::Normal => "normal".fmt(f),
+            Priority::High => "high".fmt(f),
+            Priority::Emergency => "emergency".fmt(f),
+        }
+    }
 }
 
 /// Interface for notifying the user.
 pub trait Notifier {
+    /// Check that this notifier is configured correctly, so that we can
+    /// report problems before running a long job instead of after. By
+    /// default, we assume that everything is fine.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Let the user know that we're still waiting for something to happen.
+    /// Most notifiers ignore this, because it would be too noisy.
+    fn progress(&self, _progress: &Progress) -> Result<()> {
+        Ok(())
+    }
+
     /// Let the user know that their process succeed.
     fn send(&self, notification: &Notification) -> Result<()>;
 }
@@ -92,14 +554,67 @@ pub trait Notifier {
 /// Choose an appropriate notifier backend to use.  We return a `Box`
 /// containing an object that implements the trait `Notifier`, which is about
 /// as close as Rust gets to object-oriented programming.
-pub fn choose_notifier() -> Result<Box<dyn Notifier>> {
+pub fn choose_notifier(config: &Config) -> Result<Box<dyn Notifier>> {
     let name = env::var("ALERT_NOTIFIER").unwrap_or_else(|_| "pushover".to_owned());
-    match &name[..] {
-        "console" => Ok(Box::new(console::ConsoleNotifier)),
+
+    // Apply any per-notifier configuration.
+    let notifier_config = config.notifier(&name).cloned().unwrap_or_default();
+    if !notifier_config.enabled() {
+        return Err(Error::NotifierDisabled { name });
+    }
+    let templates = config.templates().merge(notifier_config.templates());
+    let backend = notifier_config.backend().unwrap_or(&name).to_owned();
+
+    let notifier: Box<dyn Notifier> = match &backend[..] {
+        "console" => Box::new(console::ConsoleNotifier::new(templates)),
         #[cfg(feature = "notify-rust")]
-        "desktop" => Ok(Box::new(desktop::DesktopNotifier)),
-        "notifyapp" => Ok(Box::new(notifyapp::NotifyAppNotifier::new()?)),
-        "pushover" => Ok(Box::new(pushover::PushoverNotifier::new()?)),
-        _ => Err(Error::UnknownNotifier { name }),
+        "desktop" => {
+            Box::new(desktop::DesktopNotifier::new(&notifier_config, templates)?)
+        }
+        "notifyapp" => Box::new(notifyapp::NotifyAppNotifier::new(
+            &notifier_config,
+            templates,
+        )?),
+        "pushover" => Box::new(pushover::PushoverNotifier::new(
+            &notifier_config,
+            templates,
+            config.uses_priority(Priority::Emergency),
+        )?),
+        _ => return Err(Error::UnknownNotifier { name: backend }),
+    };
+
+    // Only send the outcomes the user asked for, if they asked.
+    match notifier_config.outcomes() {
+        Some(outcomes) => Ok(Box::new(OutcomeFilter {
+            notifier,
+            outcomes: outcomes.to_owned(),
+        })),
+        None => Ok(notifier),
+    }
+}
+
+/// Wraps another notifier, and drops any notifications whose outcome isn't
+/// in `outcomes`.
+struct OutcomeFilter {
+    notifier: Box<dyn Notifier>,
+    outcomes: Vec<Outcome>,
+}
+
+impl Notifier for OutcomeFilter {
+    fn validate(&self) -> Result<()> {
+        self.notifier.validate()
+    }
+
+    fn progress(&self, progress: &Progress) -> Result<()> {
+        self.notifier.progress(progress)
+    }
+
+    fn send(&self, notification: &Notification) -> Result<()> {
+        if self.outcomes.contains(&notification.outcome) {
+            self.notifier.send(notification)
+        } else {
+            debug!("Not sending {} notification", notification.outcome);
+            Ok(())
+        }
     }
 }
@@ -4,26 +4,44 @@
 
 use log::debug;
 
-use super::{Notification, Notifier};
+use super::{Notification, Notifier, Templates};
+use crate::config::NotifierConfig;
 use crate::errors::*;
-use crate::util::env_var;
 
 /// Notify the user of an event using the open source Notify app.
 pub struct NotifyAppNotifier {
     /// Registration key.
     key: String,
+    /// Templates for our titles and messages.
+    templates: Templates,
 }
 
 impl NotifyAppNotifier {
     /// Create a new notifier and configure it automatically.
-    pub fn new() -> Result<NotifyAppNotifier> {
+    pub fn new(
+        config: &NotifierConfig,
+        templates: Templates,
+    ) -> Result<NotifyAppNotifier> {
         Ok(NotifyAppNotifier {
-            key: env_var("NOTIFYAPP_KEY")?,
+            key: config.option_or_env("key", "NOTIFYAPP_KEY")?,
+            templates,
         })
     }
 }
 
 impl Notifier for NotifyAppNotifier {
+    fn validate(&self) -> Result<()> {
+        // The Notify app has no way to check keys, so just catch the obvious
+        // mistake.
+        if self.key.trim().is_empty() {
+            return Err(Error::InvalidNotifierConfig {
+                service: "Notify app".to_owned(),
+                reason: "key is empty".to_owned(),
+            });
+        }
+        Ok(())
+    }
+
     fn send(&self, notification: &Notification) -> Result<()> {
         let client = reqwest::blocking::Client::new();
 
@@ -32,8 +50,8 @@ impl Notifier for NotifyAppNotifier {
             base_url,
             &[
                 ("to", &self.key),
-                ("title", &notification.title()),
-                ("text", &notification.message()),
+                ("title", &notification.title(&self.templates)),
+                ("text", &notification.message(&self.templates)),
             ],
         )?;
 
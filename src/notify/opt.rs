@@ -0,0 +1,96 @@
+//! Command-line options shared by every subcommand which sends notifications.
+
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use super::{Notification, Outcome, Priority};
+use crate::config::Config;
+use crate::errors::*;
+
+/// Options controlling the notifications we send.
+#[derive(Debug, StructOpt)]
+pub struct NotifyOpt {
+    /// Set a custom field which can be used in templates.
+    #[structopt(
+        long = "field",
+        value_name = "KEY=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = parse_field)
+    )]
+    fields: Vec<(String, String)>,
+
+    /// Override the priority of the notification.
+    #[structopt(
+        long = "priority",
+        value_name = "PRIORITY",
+        possible_values = &["lowest", "low", "normal", "high", "emergency"]
+    )]
+    priority: Option<Priority>,
+
+    /// Include a link to this URL in the notification.
+    #[structopt(long = "url", value_name = "URL")]
+    url: Option<String>,
+
+    /// The title to display for --url.
+    #[structopt(long = "url-title", value_name = "TITLE")]
+    url_title: Option<String>,
+
+    /// Attach a file (typically an image) to the notification, if the
+    /// notifier supports it.
+    #[structopt(long = "attach", value_name = "PATH", parse(from_os_str))]
+    attachment: Option<PathBuf>,
+
+    /// A log file written by the command, which notifiers may offer to open.
+    #[structopt(long = "log-file", value_name = "PATH", parse(from_os_str))]
+    log_file: Option<PathBuf>,
+}
+
+impl NotifyOpt {
+    /// Create a new notification, filling in any custom fields and priorities
+    /// from our config file and our command-line options.
+    pub fn notification(&self, config: &Config, outcome: Outcome) -> Notification {
+        let mut notification = Notification::new(outcome);
+        if let Some(priority) = self.priority.or_else(|| config.priority(outcome)) {
+            notification = notification.with_priority(priority);
+        }
+        for (key, value) in config.fields() {
+            notification = notification.field(key.to_owned(), value.to_owned());
+        }
+        for (key, value) in &self.fields {
+            notification = notification.field(key.to_owned(), value.to_owned());
+        }
+        if let Some(url) = &self.url {
+            notification = notification.with_url(url.to_owned());
+        }
+        if let Some(url_title) = &self.url_title {
+            notification = notification.with_url_title(url_title.to_owned());
+        }
+        if let Some(attachment) = &self.attachment {
+            notification = notification.with_attachment(attachment.to_owned());
+        }
+        if let Some(log_file) = &self.log_file {
+            notification = notification.with_log_file(log_file.to_owned());
+        }
+        notification
+    }
+}
+
+/// Parse a `KEY=VALUE` pair.
+fn parse_field(s: &str) -> Result<(String, String)> {
+    match s.find('=') {
+        Some(idx) if idx > 0 => Ok((s[..idx].to_owned(), s[idx + 1..].to_owned())),
+        _ => Err(Error::InvalidField {
+            field: s.to_owned(),
+        }),
+    }
+}
+
+#[test]
+fn fields_are_parsed() {
+    assert_eq!(
+        parse_field("team=data=eng").unwrap(),
+        ("team".to_owned(), "data=eng".to_owned())
+    );
+    assert!(parse_field("=value").is_err());
+    assert!(parse_field("novalue").is_err());
+}
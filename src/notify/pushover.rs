@@ -5,11 +5,51 @@
 //! Pushover is a trademark of Superblock, LLC. This library is not associated
 //! with Superblock, LLC in any way.
 
-use log::debug;
+use log::{debug, warn};
+use reqwest::blocking::multipart;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
 
-use super::{Notification, Notifier, Outcome};
+use super::{Notification, Notifier, Outcome, Priority, Templates};
+use crate::command::Command;
+use crate::config::NotifierConfig;
 use crate::errors::*;
-use crate::util::env_var;
+use crate::util::cache_dir;
+
+/// The base URL for the Pushover API.
+const API_URL: &str = "https://api.pushover.net/1";
+
+/// How often should Pushover retry emergency notifications, in seconds?
+const DEFAULT_RETRY: u32 = 60;
+
+/// How long should Pushover keep retrying emergency notifications, in seconds?
+const DEFAULT_EXPIRE: u32 = 3600;
+
+/// Pushover's maximum title length, in characters.
+const MAX_TITLE_LEN: usize = 250;
+
+/// Pushover's maximum message length, in characters.
+const MAX_MESSAGE_LEN: usize = 1024;
+
+/// Pushover's maximum URL length, in characters.
+const MAX_URL_LEN: usize = 512;
+
+/// Pushover's maximum URL title length, in characters.
+const MAX_URL_TITLE_LEN: usize = 100;
+
+/// How long should we remember that our credentials are valid?
+const VALIDATION_CACHE_TIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often should we check whether an emergency notification has been
+/// acknowledged? Pushover asks that we don't poll more than once every 5
+/// seconds.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Notify the user of an event using the pushover.net service from
 /// Superblock, LLC.
@@ -18,45 +58,415 @@ pub struct PushoverNotifier {
     token: String,
     /// Per-user key to specify who should be notified.
     user: String,
+    /// The device to notify, if we shouldn't notify all of the user's devices.
+    device: Option<String>,
+    /// Sounds to use for each outcome, overriding our defaults.
+    sounds: HashMap<Outcome, String>,
+    /// A default URL to include in notifications.
+    url: Option<String>,
+    /// A default title for `url`.
+    url_title: Option<String>,
+    /// Should messages be formatted as HTML?
+    html: bool,
+    /// How many seconds should notifications be kept before being deleted?
+    ttl: Option<u32>,
+    /// How often to retry emergency notifications, in seconds.
+    retry: u32,
+    /// How long to keep retrying emergency notifications, in seconds.
+    expire: u32,
+    /// Should we wait for emergency notifications to be acknowledged?
+    wait_for_ack: bool,
+    /// A shell command to run when an emergency notification is acknowledged.
+    ack_hook: Option<String>,
+    /// Should we check our credentials with Pushover before running commands?
+    validate: bool,
+    /// Might we have sent emergency notifications which need to be cancelled
+    /// when a job succeeds?
+    cancel_emergencies: bool,
+    /// Templates for our titles and messages.
+    templates: Templates,
 }
 
 impl PushoverNotifier {
-    /// Create a new notifier and configure it automatically.
-    pub fn new() -> Result<PushoverNotifier> {
+    /// Create a new notifier and configure it automatically. If
+    /// `cancel_emergencies` is true, successes will cancel any emergency
+    /// notifications for earlier failures.
+    pub fn new(
+        config: &NotifierConfig,
+        templates: Templates,
+        cancel_emergencies: bool,
+    ) -> Result<PushoverNotifier> {
+        let mut sounds = HashMap::new();
+        for &outcome in Outcome::all() {
+            if let Some(sound) = config.option(&format!("{}_sound", outcome)) {
+                sounds.insert(outcome, sound.to_owned());
+            }
+        }
+
         Ok(PushoverNotifier {
-            token: env_var("PUSHOVER_TOKEN")?,
-            user: env_var("PUSHOVER_USER")?,
+            token: config.option_or_env("token", "PUSHOVER_TOKEN")?,
+            user: config.option_or_env("user", "PUSHOVER_USER")?,
+            device: config.option("device").map(|d| d.to_owned()),
+            sounds,
+            url: config.option("url").map(|u| u.to_owned()),
+            url_title: config.option("url_title").map(|t| t.to_owned()),
+            html: config.parse_option("html")?.unwrap_or(false),
+            ttl: config.parse_option("ttl")?,
+            retry: config.parse_option("retry")?.unwrap_or(DEFAULT_RETRY),
+            expire: config.parse_option("expire")?.unwrap_or(DEFAULT_EXPIRE),
+            wait_for_ack: config.parse_option("wait_for_ack")?.unwrap_or(false),
+            ack_hook: config.option("ack_hook").map(|h| h.to_owned()),
+            validate: config.parse_option("validate")?.unwrap_or(false),
+            cancel_emergencies,
+            templates,
         })
     }
-}
 
-impl Notifier for PushoverNotifier {
-    fn send(&self, notification: &Notification) -> Result<()> {
-        let sound = match notification.outcome() {
+    /// Which sound should we play for `outcome`?
+    fn sound(&self, outcome: Outcome) -> &str {
+        if let Some(sound) = self.sounds.get(&outcome) {
+            return sound;
+        }
+        match outcome {
             Outcome::Success => "classical",
             Outcome::Failure | Outcome::Timeout => "tugboat",
-        };
+            Outcome::Interrupted => "falling",
+            Outcome::Error => "siren",
+            Outcome::Changed => "incoming",
+            Outcome::Finished => "pushover",
+        }
+    }
+
+    /// Cancel any outstanding emergency notifications with `tag`.
+    fn cancel_emergency(
+        &self,
+        client: &reqwest::blocking::Client,
+        tag: &str,
+    ) -> Result<()> {
+        debug!("Cancelling pushover emergency notifications tagged {}", tag);
+        let response = client
+            .post(&format!("{}/receipts/cancel_by_tag/{}.json", API_URL, tag))
+            .form(&[("token", &self.token)])
+            .send()?;
+        debug!("Pushover response: {:?}", response.status());
+        check_response(response).map(|_| ())
+    }
+
+    /// Wait until an emergency notification is acknowledged or expires, and
+    /// run our hook if someone acknowledges it.
+    fn wait_for_receipt(
+        &self,
+        client: &reqwest::blocking::Client,
+        receipt: &str,
+    ) -> Result<()> {
+        let url = format!("{}/receipts/{}.json", API_URL, receipt);
+        loop {
+            thread::sleep(RECEIPT_POLL_INTERVAL);
+            debug!("Checking pushover receipt {}", receipt);
+            let response = client.get(&url).query(&[("token", &self.token)]).send()?;
+            let status: ReceiptResponse = check_response(response)?.json()?;
+            if status.acknowledged != 0 {
+                let by = status.acknowledged_by.unwrap_or_default();
+                debug!("Emergency notification acknowledged by {}", by);
+                if let Some(hook) = &self.ack_hook {
+                    run_ack_hook(hook, &by)?;
+                }
+                return Ok(());
+            } else if status.expired != 0 {
+                debug!("Emergency notification expired without acknowledgement");
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Notifier for PushoverNotifier {
+    fn validate(&self) -> Result<()> {
+        if !self.validate {
+            return Ok(());
+        }
+
+        // Only check our credentials occasionally, because this slows down
+        // every command.
+        let key = format!(
+            "{}\0{}\0{}",
+            self.token,
+            self.user,
+            self.device.as_deref().unwrap_or_default(),
+        );
+        let cache_path = cache_dir()
+            .map(|dir| dir.join(format!("pushover-valid-{:016x}", stable_hash(&key))));
+        if let Some(cache_path) = &cache_path {
+            if recently_touched(cache_path) {
+                debug!("Pushover credentials were validated recently");
+                return Ok(());
+            }
+        }
 
         let client = reqwest::blocking::Client::new();
-        let params = [
-            ("token", &self.token[..]),
-            ("user", &self.user[..]),
-            ("title", &notification.title()),
-            ("sound", sound),
-            ("message", &notification.message()),
-        ];
-        debug!("Sending notification via pushover");
+        let mut params = vec![("token", &self.token), ("user", &self.user)];
+        if let Some(device) = &self.device {
+            params.push(("device", device));
+        }
+        debug!("Validating pushover credentials");
         let response = client
-            .post("https://api.pushover.net/1/messages.json")
+            .post(&format!("{}/users/validate.json", API_URL))
             .form(&params)
             .send()?;
         debug!("Pushover response: {:?}", response.status());
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err(Error::CouldNotSendNotification {
+        let validation: ValidateResponse = response.json()?;
+        if validation.status != 1 {
+            let reason = if validation.errors.is_empty() {
+                "credentials were rejected".to_owned()
+            } else {
+                validation.errors.join(", ")
+            };
+            return Err(Error::InvalidNotifierConfig {
                 service: "pushover.net".to_owned(),
-            })
+                reason,
+            });
+        }
+
+        if let Some(cache_path) = &cache_path {
+            if let Err(err) = touch(cache_path) {
+                warn!("Could not write {}: {}", cache_path.display(), err);
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, notification: &Notification) -> Result<()> {
+        let tag = job_tag(notification);
+
+        let client = reqwest::blocking::Client::new();
+
+        // If this job succeeded, stop paging people about any earlier failure.
+        // This isn't worth failing over, because we still want to send our
+        // real notification.
+        if let (true, Outcome::Success, Some(tag)) =
+            (self.cancel_emergencies, notification.outcome(), &tag)
+        {
+            if let Err(err) = self.cancel_emergency(&client, tag) {
+                warn!("Could not cancel pushover emergency notification: {}", err);
+            }
+        }
+
+        let mut params = vec![
+            ("token", self.token.clone()),
+            ("user", self.user.clone()),
+            (
+                "title",
+                truncate(&notification.title(&self.templates), MAX_TITLE_LEN),
+            ),
+            ("sound", self.sound(notification.outcome()).to_owned()),
+            (
+                "message",
+                truncate(&notification.message(&self.templates), MAX_MESSAGE_LEN),
+            ),
+            (
+                "priority",
+                pushover_priority(notification.priority()).to_string(),
+            ),
+        ];
+        if let Ok(timestamp) = notification.finished_at().duration_since(UNIX_EPOCH) {
+            params.push(("timestamp", timestamp.as_secs().to_string()));
+        }
+        if let Some(device) = &self.device {
+            params.push(("device", device.to_owned()));
+        }
+        if let Some(url) = notification.url().or(self.url.as_deref()) {
+            params.push(("url", truncate(url, MAX_URL_LEN)));
+        }
+        let url_title = notification.url_title().or(self.url_title.as_deref());
+        if let Some(url_title) = url_title {
+            params.push(("url_title", truncate(url_title, MAX_URL_TITLE_LEN)));
+        }
+        if self.html {
+            params.push(("html", "1".to_owned()));
+        }
+        let emergency = notification.priority() == Priority::Emergency;
+        if emergency {
+            params.push(("retry", self.retry.to_string()));
+            params.push(("expire", self.expire.to_string()));
+            if let Some(tag) = tag {
+                params.push(("tags", tag));
+            }
+        } else if let Some(ttl) = self.ttl {
+            // Pushover doesn't allow a TTL for emergency notifications.
+            params.push(("ttl", ttl.to_string()));
+        }
+
+        debug!("Sending notification via pushover");
+        let request = client.post(&format!("{}/messages.json", API_URL));
+        let request = match notification.attachment() {
+            Some(path) => {
+                let mut form = multipart::Form::new();
+                for (key, value) in params {
+                    form = form.text(key, value);
+                }
+                let form = form.file("attachment", path).map_err(|source| {
+                    Error::CouldNotReadAttachment {
+                        path: path.to_owned(),
+                        source,
+                    }
+                })?;
+                request.multipart(form)
+            }
+            None => request.form(&params),
+        };
+        let response = request.send()?;
+        debug!("Pushover response: {:?}", response.status());
+        let message: MessageResponse = check_response(response)?.json()?;
+
+        if emergency && (self.wait_for_ack || self.ack_hook.is_some()) {
+            if let Some(receipt) = message.receipt {
+                eprintln!("Waiting for emergency notification to be acknowledged");
+                // We've already sent the notification, so don't report
+                // failure just because we lost track of it.
+                if let Err(err) = self.wait_for_receipt(&client, &receipt) {
+                    warn!("Could not check pushover receipt {}: {}", receipt, err);
+                }
+            }
         }
+        Ok(())
+    }
+}
+
+/// The parts of a `messages.json` response that we care about.
+#[derive(Debug, Deserialize)]
+struct MessageResponse {
+    /// A receipt which we can use to check on emergency notifications.
+    receipt: Option<String>,
+}
+
+/// The parts of a `receipts/*.json` response that we care about.
+#[derive(Debug, Deserialize)]
+struct ReceiptResponse {
+    /// 1 if the notification has been acknowledged.
+    acknowledged: u8,
+    /// The user who acknowledged the notification.
+    acknowledged_by: Option<String>,
+    /// 1 if Pushover has given up retrying.
+    expired: u8,
+}
+
+/// The parts of a `users/validate.json` response that we care about.
+#[derive(Debug, Deserialize)]
+struct ValidateResponse {
+    /// 1 if the credentials are valid.
+    status: u8,
+    /// Why the credentials were rejected.
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// Convert HTTP errors into our own error type.
+fn check_response(
+    response: reqwest::blocking::Response,
+) -> Result<reqwest::blocking::Response> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Error::CouldNotSendNotification {
+            service: "pushover.net".to_owned(),
+        })
+    }
+}
+
+/// Truncate `s` to at most `max_len` characters, marking where we cut it off.
+fn truncate(s: &str, max_len: usize) -> String {
+    if s.chars().count() <= max_len {
+        s.to_owned()
+    } else {
+        let mut truncated = s.chars().take(max_len - 1).collect::<String>();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Convert a `Priority` to a Pushover priority level.
+fn pushover_priority(priority: Priority) -> i8 {
+    match priority {
+        Priority::Lowest => -2,
+        Priority::Low => -1,
+        Priority::Normal => 0,
+        Priority::High => 1,
+        Priority::Emergency => 2,
     }
 }
+
+/// Generate a tag identifying the job that `notification` describes, so that
+/// a later success can cancel an emergency notification for an earlier
+/// failure. Notifications without a command can't be told apart, so they
+/// don't get a tag.
+fn job_tag(notification: &Notification) -> Option<String> {
+    let command = notification.variable("command")?;
+    Some(format!("alert-{:016x}", stable_hash(&command)))
+}
+
+/// Hash `s` using 64-bit FNV-1a. Unlike Rust's `DefaultHasher`, this gives the
+/// same result in every build of `alert`, so tags and cache files stay valid
+/// when we upgrade.
+fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Was the file at `path` modified within `VALIDATION_CACHE_TIME`?
+fn recently_touched(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age < VALIDATION_CACHE_TIME)
+        .unwrap_or(false)
+}
+
+/// Create or update the file at `path`.
+fn touch(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, "")
+}
+
+/// Run the user's acknowledgement hook.
+fn run_ack_hook(hook: &str, acknowledged_by: &str) -> Result<()> {
+    let cmd =
+        Command::from_slice(&["sh".to_owned(), "-c".to_owned(), hook.to_owned()])?;
+    let status = process::Command::new(&cmd.cmd)
+        .args(&cmd.args)
+        .env("PUSHOVER_ACKNOWLEDGED_BY", acknowledged_by)
+        .status()
+        .map_err(|source| Error::CouldNotRun {
+            cmd: cmd.clone(),
+            source,
+        })?;
+    if !status.success() {
+        warn!("Acknowledgement hook failed with {}", status);
+    }
+    Ok(())
+}
+
+#[test]
+fn long_text_is_truncated() {
+    assert_eq!(truncate("short", 10), "short");
+    assert_eq!(truncate("exactly 10", 10), "exactly 10");
+    assert_eq!(truncate("much too long", 10), "much too …");
+    assert_eq!(truncate("ééééé", 3).chars().count(), 3);
+}
+
+#[test]
+fn only_commands_are_tagged() {
+    assert!(job_tag(&Notification::new(Outcome::Success)).is_none());
+    let cmd = Command::from_slice(&["make".to_owned()]).unwrap();
+    let notification = Notification::new(Outcome::Success).with_command(cmd);
+    assert!(job_tag(&notification).unwrap().starts_with("alert-"));
+}
+
+#[test]
+fn stable_hashes_do_not_change() {
+    assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+}
@@ -0,0 +1,205 @@
+//! User-configurable templates for notification titles and messages.
+//!
+//! Templates are ordinary strings containing `{name}` placeholders, which are
+//! replaced with values from the notification. Use `{{` and `}}` to include
+//! literal braces.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::Outcome;
+use crate::errors::*;
+
+/// A parsed template.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    /// The original source of this template, for error messages and
+    /// serialization.
+    source: String,
+    /// The parsed pieces of this template.
+    segments: Vec<Segment>,
+}
+
+/// Part of a template.
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    /// Literal text.
+    Text(String),
+    /// A `{name}` placeholder.
+    Var(String),
+}
+
+impl Template {
+    /// Parse a template.
+    pub fn parse(source: &str) -> Result<Template> {
+        let invalid = |reason: &str| Error::InvalidTemplate {
+            template: source.to_owned(),
+            reason: reason.to_owned(),
+        };
+
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') => return Err(invalid("unexpected '{'")),
+                            Some(c) => name.push(c),
+                            None => return Err(invalid("unclosed '{'")),
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err(invalid("empty placeholder"));
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(text.split_off(0)));
+                    }
+                    segments.push(Segment::Var(name.to_owned()));
+                }
+                '}' => return Err(invalid("unmatched '}'")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Template {
+            source: source.to_owned(),
+            segments,
+        })
+    }
+
+    /// Render this template, calling `lookup` to get the value of each
+    /// placeholder. Unknown placeholders are replaced with the empty string.
+    pub fn render<F>(&self, lookup: F) -> String
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Var(name) => {
+                    if let Some(value) = lookup(name) {
+                        out.push_str(&value);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = Error;
+
+    fn try_from(source: String) -> Result<Template> {
+        Template::parse(&source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> String {
+        template.source
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+/// Templates for a single `Outcome`. Any template which isn't specified
+/// falls back to our defaults.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutcomeTemplates {
+    /// The title of the notification.
+    pub title: Option<Template>,
+    /// The body of the notification.
+    pub message: Option<Template>,
+}
+
+/// A full set of templates, keyed by `Outcome`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Templates {
+    outcomes: HashMap<Outcome, OutcomeTemplates>,
+}
+
+impl Templates {
+    /// Combine two sets of templates, preferring those in `overrides`.
+    pub fn merge(&self, overrides: &Templates) -> Templates {
+        let mut outcomes = self.outcomes.clone();
+        for (outcome, templates) in &overrides.outcomes {
+            let entry = outcomes.entry(*outcome).or_default();
+            if templates.title.is_some() {
+                entry.title = templates.title.clone();
+            }
+            if templates.message.is_some() {
+                entry.message = templates.message.clone();
+            }
+        }
+        Templates { outcomes }
+    }
+
+    /// Get the title template for `outcome`, if the user specified one.
+    pub fn title(&self, outcome: Outcome) -> Option<&Template> {
+        self.outcomes.get(&outcome).and_then(|t| t.title.as_ref())
+    }
+
+    /// Get the message template for `outcome`, if the user specified one.
+    pub fn message(&self, outcome: Outcome) -> Option<&Template> {
+        self.outcomes.get(&outcome).and_then(|t| t.message.as_ref())
+    }
+}
+
+#[test]
+fn templates_replace_placeholders() {
+    let template = Template::parse("{{{name}}} took {duration}{missing}").unwrap();
+    let rendered = template.render(|name| match name {
+        "name" => Some("job".to_owned()),
+        "duration" => Some("5s".to_owned()),
+        _ => None,
+    });
+    assert_eq!(rendered, "{job} took 5s");
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    assert!(Template::parse("{unclosed").is_err());
+    assert!(Template::parse("unmatched}").is_err());
+    assert!(Template::parse("{}").is_err());
+}
+
+#[test]
+fn templates_can_be_loaded_from_toml() {
+    let templates: Templates = toml::from_str(
+        r#"
+[failure]
+title = "{command} failed on {host}"
+"#,
+    )
+    .unwrap();
+    assert!(templates.title(Outcome::Failure).is_some());
+    assert!(templates.message(Outcome::Failure).is_none());
+    assert!(templates.title(Outcome::Success).is_none());
+}
@@ -0,0 +1,256 @@
+//! Reading output from child processes a line at a time.
+
+use std::collections::VecDeque;
+use std::io::{self, prelude::*};
+use std::process::Child;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::errors::*;
+
+/// Which output stream a line came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// The usual name of this stream.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A line of output from a child process, without the trailing newline.
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub stream: Stream,
+    pub text: String,
+}
+
+impl Line {
+    /// Print this line to the stream it came from.
+    pub fn echo(&self) -> Result<()> {
+        let result = match self.stream {
+            Stream::Stdout => writeln!(io::stdout(), "{}", self.text),
+            Stream::Stderr => writeln!(io::stderr(), "{}", self.text),
+        };
+        result.map_err(|source| Error::CouldNotWriteToStdio {
+            dest: self.stream.name(),
+            source,
+        })
+    }
+}
+
+/// Read lines from the standard output and standard error of `child`, which
+/// must both be piped. Lines are sent to the returned channel as soon as they
+/// arrive, and the channel is closed once both streams have been closed.
+///
+/// On Unix, we read both streams from a single thread using `poll`, so lines
+/// arrive in the same order the command wrote them, unless it writes to both
+/// streams at almost exactly the same time. But many programs buffer their
+/// standard output when it's a pipe, and only write it in large blocks, so
+/// its lines may still arrive after later lines of standard error.
+#[cfg(unix)]
+pub fn read_lines(child: &mut Child) -> Receiver<Line> {
+    use std::os::unix::io::AsRawFd;
+
+    let (tx, rx) = mpsc::channel();
+    let mut sources = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        let fd = stdout.as_raw_fd();
+        sources.push(Source::new(Box::new(stdout), fd, Stream::Stdout));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let fd = stderr.as_raw_fd();
+        sources.push(Source::new(Box::new(stderr), fd, Stream::Stderr));
+    }
+    thread::spawn(move || poll_sources(sources, &tx));
+    rx
+}
+
+#[cfg(not(unix))]
+pub fn read_lines(child: &mut Child) -> Receiver<Line> {
+    let (tx, rx) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        spawn_reader(stdout, Stream::Stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_reader(stderr, Stream::Stderr, tx);
+    }
+    rx
+}
+
+/// One of the streams we're reading using `poll`.
+#[cfg(unix)]
+struct Source {
+    reader: Box<dyn Read + Send>,
+    fd: std::os::unix::io::RawFd,
+    stream: Stream,
+    /// Output which we haven't sent yet, because it's not a complete line.
+    partial: Vec<u8>,
+    open: bool,
+}
+
+#[cfg(unix)]
+impl Source {
+    fn new(
+        reader: Box<dyn Read + Send>,
+        fd: std::os::unix::io::RawFd,
+        stream: Stream,
+    ) -> Source {
+        Source {
+            reader,
+            fd,
+            stream,
+            partial: vec![],
+            open: true,
+        }
+    }
+
+    /// Read whatever is available, and send any complete lines to `tx`.
+    /// Returns false if nobody is listening.
+    fn read(&mut self, tx: &Sender<Line>) -> bool {
+        let mut buf = [0; 4096];
+        match self.reader.read(&mut buf) {
+            Ok(0) | Err(_) => return self.close(tx),
+            Ok(count) => self.partial.extend_from_slice(&buf[..count]),
+        }
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line = self.partial.drain(..=end).collect::<Vec<_>>();
+            if !self.send(tx, &line) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Stop reading, and send anything left over, even without a newline.
+    /// Returns false if nobody is listening.
+    fn close(&mut self, tx: &Sender<Line>) -> bool {
+        self.open = false;
+        if self.partial.is_empty() {
+            return true;
+        }
+        let partial = std::mem::take(&mut self.partial);
+        self.send(tx, &partial)
+    }
+
+    /// Send `line` to `tx`. Returns false if nobody is listening.
+    fn send(&self, tx: &Sender<Line>, line: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(line)
+            .trim_end_matches(&['\n', '\r'][..])
+            .to_owned();
+        let stream = self.stream;
+        tx.send(Line { stream, text }).is_ok()
+    }
+}
+
+/// Read from `sources` as output becomes available, until they're all
+/// closed.
+#[cfg(unix)]
+fn poll_sources(mut sources: Vec<Source>, tx: &Sender<Line>) {
+    while !sources.is_empty() {
+        let mut fds = sources
+            .iter()
+            .map(|source| libc::pollfd {
+                fd: source.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect::<Vec<_>>();
+        // SAFETY: `fds` is valid for the duration of the call.
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) };
+        if ready < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+        for (source, fd) in sources.iter_mut().zip(&fds) {
+            // `POLLHUP` means the other end was closed, but there may still
+            // be output to read before we get end of file.
+            let keep_going = if fd.revents & (libc::POLLIN | libc::POLLHUP) != 0 {
+                source.read(tx)
+            } else if fd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+                source.close(tx)
+            } else {
+                true
+            };
+            if !keep_going {
+                return;
+            }
+        }
+        sources.retain(|source| source.open);
+    }
+}
+
+/// Send each line from `reader` to `tx` in a background thread.
+#[cfg(not(unix))]
+fn spawn_reader<R>(reader: R, stream: Stream, tx: Sender<Line>)
+where
+    R: Read + Send + 'static,
+{
+    use std::io::BufReader;
+
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let text = String::from_utf8_lossy(&buf)
+                .trim_end_matches(&['\n', '\r'][..])
+                .to_owned();
+            if tx.send(Line { stream, text }).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// The last few lines of output.
+#[derive(Clone, Debug)]
+pub struct Tail {
+    lines: VecDeque<String>,
+    max_lines: usize,
+}
+
+impl Tail {
+    /// Create a new `Tail` which remembers up to `max_lines` lines.
+    pub fn new(max_lines: usize) -> Tail {
+        Tail {
+            lines: VecDeque::with_capacity(max_lines),
+            max_lines,
+        }
+    }
+
+    /// Remember `line`, forgetting the oldest line if necessary.
+    pub fn push(&mut self, line: &str) {
+        if self.lines.len() == self.max_lines {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.to_owned());
+    }
+
+    /// Join our lines into a single string.
+    pub fn to_text(&self) -> String {
+        self.lines.iter().cloned().collect::<Vec<_>>().join("\n")
+    }
+}
+
+#[test]
+fn tail_keeps_the_last_lines() {
+    let mut tail = Tail::new(2);
+    for line in &["a", "b", "c"] {
+        tail.push(line);
+    }
+    assert_eq!(tail.to_text(), "b\nc");
+}
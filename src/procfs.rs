@@ -0,0 +1,255 @@
+//! Information about other processes, read from `/proc`. This only works on
+//! Linux.
+
+use log::debug;
+use regex::Regex;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, ExitStatus};
+use std::thread;
+use std::time::Duration;
+
+use crate::command::Command;
+use crate::errors::*;
+
+/// Read a file from `/proc/{pid}`.
+fn read(pid: u32, name: &str) -> Result<Vec<u8>> {
+    let path = PathBuf::from(format!("/proc/{}/{}", pid, name));
+    fs::read(&path).map_err(|source| Error::CouldNotReadProcess { path, source })
+}
+
+/// Get the command line of process `pid`.
+pub fn command(pid: u32) -> Result<Command> {
+    let cmdline = read(pid, "cmdline")?;
+    let args = cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect::<Vec<_>>();
+    Command::from_slice(&args)
+}
+
+/// The parts of `/proc/{pid}/stat` that we care about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stat {
+    /// The state of the process. `Z` means that it has exited, but its
+    /// parent hasn't collected its exit status yet.
+    state: char,
+    /// The ID of the parent process.
+    ppid: u32,
+    /// When the process started, in clock ticks since boot. We use this to
+    /// notice if a PID has been reused.
+    start_time: u64,
+}
+
+/// Read `/proc/{pid}/stat`.
+fn stat(pid: u32) -> Result<Stat> {
+    let stat = String::from_utf8_lossy(&read(pid, "stat")?).into_owned();
+    // The process name may contain spaces or parentheses, so skip past the
+    // last `)` before splitting. `state` is field 3, `ppid` is field 4 and
+    // `starttime` is field 22, counting from 1.
+    let fields = stat
+        .rfind(')')
+        .map(|end| stat[end + 1..].split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let state = fields.first().and_then(|s| s.chars().next());
+    let ppid = fields.get(1).and_then(|s| s.parse().ok());
+    let start_time = fields.get(19).and_then(|s| s.parse().ok());
+    match (state, ppid, start_time) {
+        (Some(state), Some(ppid), Some(start_time)) => Ok(Stat {
+            state,
+            ppid,
+            start_time,
+        }),
+        _ => Err(Error::CouldNotParseProcess { pid }),
+    }
+}
+
+/// How long has process `pid` been running?
+#[cfg(target_os = "linux")]
+pub fn elapsed(pid: u32) -> Result<Duration> {
+    let start_ticks = stat(pid)?.start_time;
+    let uptime = fs::read_to_string("/proc/uptime").unwrap_or_default();
+    let uptime_secs = uptime
+        .split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .ok_or(Error::CouldNotParseProcess { pid })?;
+    // SAFETY: `sysconf` has no memory-safety requirements.
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+    let elapsed = uptime_secs - start_ticks as f64 / ticks_per_sec;
+    Ok(Duration::from_secs_f64(elapsed.max(0.0)))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn elapsed(pid: u32) -> Result<Duration> {
+    Err(Error::CouldNotParseProcess { pid })
+}
+
+/// Our own process ID, and those of our ancestors.
+fn ancestors() -> Vec<u32> {
+    let mut pids = vec![process::id()];
+    while let Ok(stat) = stat(pids[pids.len() - 1]) {
+        if stat.ppid == 0 || pids.contains(&stat.ppid) {
+            break;
+        }
+        pids.push(stat.ppid);
+    }
+    pids
+}
+
+/// Find the process whose command line matches `pattern`. We ignore
+/// ourselves and the shells that started us, because their command lines
+/// contain `pattern`.
+pub fn find(pattern: &Regex) -> Result<u32> {
+    let ancestors = ancestors();
+    let entries =
+        fs::read_dir("/proc").map_err(|source| Error::CouldNotReadProcess {
+            path: PathBuf::from("/proc"),
+            source,
+        })?;
+    let mut pids = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| !ancestors.contains(pid))
+        .filter(|&pid| match command(pid) {
+            Ok(cmd) => pattern.is_match(&cmd.to_string()),
+            // The process has exited, or it's a kernel thread.
+            Err(_) => false,
+        })
+        .collect::<Vec<_>>();
+    pids.sort_unstable();
+    match pids.len() {
+        0 => Err(Error::NoMatchingProcess {
+            pattern: pattern.as_str().to_owned(),
+        }),
+        1 => Ok(pids[0]),
+        _ => Err(Error::TooManyMatchingProcesses {
+            pattern: pattern.as_str().to_owned(),
+            pids,
+        }),
+    }
+}
+
+/// Wait for process `pid` to exit, checking every `interval`. If we're
+/// allowed to trace the process, we also return its exit status.
+pub fn wait(pid: u32, interval: Duration) -> Result<Option<ExitStatus>> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(status) = ptrace::wait(pid)? {
+            return Ok(Some(status));
+        }
+    }
+
+    let start_time = stat(pid)?.start_time;
+    loop {
+        match stat(pid) {
+            Ok(stat) if stat.start_time == start_time && stat.state != 'Z' => {
+                thread::sleep(interval);
+            }
+            _ => {
+                debug!("Process {} has exited", pid);
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Use `ptrace` to find out how a process exits. This only works if we're
+/// allowed to trace the process, which usually means that it belongs to us,
+/// and either it's one of our descendants or the system allows tracing
+/// other processes.
+#[cfg(target_os = "linux")]
+mod ptrace {
+    use log::debug;
+    use std::io;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::ptr;
+
+    use crate::errors::*;
+
+    /// Reported when a process stops because of a signal like `SIGTSTP`.
+    /// Missing from older versions of `libc`.
+    const PTRACE_EVENT_STOP: libc::c_int = 128;
+
+    /// Call `ptrace` with an integer `data` argument.
+    unsafe fn ptrace(
+        request: libc::c_uint,
+        pid: libc::pid_t,
+        data: usize,
+    ) -> libc::c_long {
+        libc::ptrace(
+            request as _,
+            pid,
+            ptr::null_mut::<libc::c_void>(),
+            data as *mut libc::c_void,
+        )
+    }
+
+    /// Wait for `pid` to exit and return its status, or `None` if we can't
+    /// trace it.
+    pub fn wait(pid: u32) -> Result<Option<ExitStatus>> {
+        let pid = pid as libc::pid_t;
+        // SAFETY: These `ptrace` calls only affect the traced process.
+        unsafe {
+            let options = libc::PTRACE_O_TRACEEXIT as usize;
+            if ptrace(libc::PTRACE_SEIZE as _, pid, options) != 0 {
+                debug!(
+                    "Could not trace process {}: {}",
+                    pid,
+                    io::Error::last_os_error()
+                );
+                return Ok(None);
+            }
+
+            loop {
+                let mut status = 0;
+                if libc::waitpid(pid, &mut status, libc::__WALL) < 0 {
+                    let source = io::Error::last_os_error();
+                    if source.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::CouldNotWaitForProcess {
+                        pid: pid as u32,
+                        source,
+                    });
+                }
+                if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                    return Ok(Some(ExitStatus::from_raw(status)));
+                }
+                if !libc::WIFSTOPPED(status) {
+                    continue;
+                }
+                match status >> 16 {
+                    libc::PTRACE_EVENT_EXIT => {
+                        // The process is about to exit, so get its status
+                        // and let it go.
+                        let mut exit_status: libc::c_ulong = 0;
+                        libc::ptrace(
+                            libc::PTRACE_GETEVENTMSG as _,
+                            pid,
+                            ptr::null_mut::<libc::c_void>(),
+                            &mut exit_status as *mut libc::c_ulong,
+                        );
+                        ptrace(libc::PTRACE_DETACH as _, pid, 0);
+                        return Ok(Some(ExitStatus::from_raw(
+                            exit_status as libc::c_int,
+                        )));
+                    }
+                    PTRACE_EVENT_STOP => {
+                        // Leave the process stopped until something wakes it.
+                        ptrace(libc::PTRACE_LISTEN as _, pid, 0);
+                    }
+                    0 => {
+                        // Deliver the signal that stopped the process.
+                        let signal = libc::WSTOPSIG(status) as usize;
+                        ptrace(libc::PTRACE_CONT as _, pid, signal);
+                    }
+                    _ => {
+                        ptrace(libc::PTRACE_CONT as _, pid, 0);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Deciding how long `alert watch` should wait between runs.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::errors::*;
+
+/// If the user asks for exponential backoff without a `--max-interval`, never
+/// wait longer than this.
+const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// With `--jitter`, vary each wait by up to this fraction of the interval.
+const JITTER: f64 = 0.1;
+
+/// How our interval changes while nothing is happening.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backoff {
+    /// Always wait for the same interval.
+    Constant,
+    /// Double the interval after each run, until the output changes.
+    Exponential,
+}
+
+impl Backoff {
+    /// The values accepted by `FromStr`, for use in `--help`.
+    pub fn variants() -> &'static [&'static str] {
+        &["constant", "exponential"]
+    }
+}
+
+impl FromStr for Backoff {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Backoff> {
+        match s {
+            "constant" => Ok(Backoff::Constant),
+            "exponential" => Ok(Backoff::Exponential),
+            _ => Err(Error::UnknownBackoff { name: s.to_owned() }),
+        }
+    }
+}
+
+/// Keeps track of how long to wait between runs.
+#[derive(Debug)]
+pub struct Schedule {
+    /// The interval the user asked for.
+    initial: Duration,
+    /// The current interval, before any jitter.
+    interval: Duration,
+    max_interval: Duration,
+    backoff: Backoff,
+    jitter: bool,
+}
+
+impl Schedule {
+    /// Create a new schedule, starting with `interval` between runs. We never
+    /// back off beyond `max_interval`.
+    pub fn new(
+        interval: Duration,
+        backoff: Backoff,
+        max_interval: Option<Duration>,
+        jitter: bool,
+    ) -> Schedule {
+        Schedule {
+            initial: interval,
+            interval,
+            max_interval: max_interval.unwrap_or(DEFAULT_MAX_INTERVAL).max(interval),
+            backoff,
+            jitter,
+        }
+    }
+
+    /// The current interval between the start of one run and the next.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// How long should we wait before the next run, counting from the start
+    /// of the previous one? This also advances our backoff.
+    pub fn next_wait(&mut self) -> Duration {
+        let wait = if self.jitter {
+            self.interval.mul_f64(1.0 + JITTER * (2.0 * random() - 1.0))
+        } else {
+            self.interval
+        };
+        if self.backoff == Backoff::Exponential {
+            self.interval = (self.interval * 2).min(self.max_interval);
+        }
+        wait
+    }
+
+    /// Something interesting happened, so go back to checking frequently.
+    pub fn reset(&mut self) {
+        self.interval = self.initial;
+    }
+}
+
+/// A random number in the range `0.0..1.0`. This is nowhere near good enough
+/// for cryptography, but it's plenty to keep lots of watchers from running in
+/// lockstep, and it saves us a dependency.
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[test]
+fn exponential_backoff_is_capped_and_reset() {
+    let secs = Duration::from_secs;
+    let mut schedule =
+        Schedule::new(secs(1), Backoff::Exponential, Some(secs(5)), false);
+    let waits = (0..5).map(|_| schedule.next_wait()).collect::<Vec<_>>();
+    assert_eq!(waits, vec![secs(1), secs(2), secs(4), secs(5), secs(5)]);
+    schedule.reset();
+    assert_eq!(schedule.next_wait(), secs(1));
+}
+
+#[test]
+fn jitter_stays_close_to_the_interval() {
+    let mut schedule =
+        Schedule::new(Duration::from_secs(10), Backoff::Constant, None, true);
+    for _ in 0..100 {
+        let wait = schedule.next_wait();
+        assert!(wait >= Duration::from_secs(9) && wait <= Duration::from_secs(11));
+    }
+}
@@ -0,0 +1,241 @@
+//! A full-screen display for `alert watch --screen`, similar to `watch(1)`.
+//! We redraw the latest output after every run on the terminal's alternate
+//! screen, instead of appending it to the scrollback. When we're done, we
+//! switch back and print the final output once, so it stays in the
+//! scrollback.
+
+use colored::*;
+use std::io::{self, prelude::*};
+use std::time::{Duration, SystemTime};
+
+use crate::command::Command;
+use crate::diff;
+use crate::errors::*;
+use crate::matcher::Matcher;
+use crate::notify::Outcome;
+use crate::output::Line;
+use crate::util::format_short_duration;
+
+/// Move the cursor to the top left and clear the screen.
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// Switch to the alternate screen, saving the cursor position.
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h";
+
+/// Switch back to the normal screen, restoring the cursor position.
+const LEAVE_ALTERNATE_SCREEN: &str = "\x1b[?1049l";
+
+/// How many lines does our header take up, including the blank line after it?
+const HEADER_LINES: usize = 4;
+
+/// The state of our display.
+pub struct Screen<'a> {
+    cmd: &'a Command,
+    matcher: &'a Matcher,
+    interval: Duration,
+    start: SystemTime,
+    /// How many times we've run the command.
+    runs: usize,
+    /// Output from the latest run.
+    lines: Vec<Line>,
+    /// Which lines of `lines` weren't in the run before that, so we can
+    /// highlight them.
+    added: Vec<bool>,
+    /// Why we couldn't run the command the last time we tried, if we
+    /// couldn't.
+    error: Option<String>,
+    /// Are we still showing the alternate screen?
+    active: bool,
+}
+
+impl<'a> Screen<'a> {
+    /// Create a new screen for watching `cmd`, and switch to the alternate
+    /// screen.
+    pub fn new(
+        cmd: &'a Command,
+        matcher: &'a Matcher,
+        interval: Duration,
+        start: SystemTime,
+    ) -> Result<Screen<'a>> {
+        write_stdout(ENTER_ALTERNATE_SCREEN)?;
+        Ok(Screen {
+            cmd,
+            matcher,
+            interval,
+            start,
+            runs: 0,
+            lines: vec![],
+            added: vec![],
+            error: None,
+            active: true,
+        })
+    }
+
+    /// Change the interval shown in our header, if we're backing off.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Record the output of another run.
+    pub fn update(&mut self, lines: &[Line]) {
+        self.runs += 1;
+        let previous = self.lines.iter().map(|l| &l.text[..]).collect::<Vec<_>>();
+        let current = lines.iter().map(|l| &l.text[..]).collect::<Vec<_>>();
+        self.added = diff::added_lines(&previous, &current);
+        self.lines = lines.to_vec();
+        self.error = None;
+    }
+
+    /// Record a run which failed because we couldn't run the command.
+    pub fn update_with_error(&mut self, error: &Error) {
+        self.update(&[]);
+        self.error = Some(error.to_string());
+    }
+
+    /// Redraw the screen. `next_run` is how long until we run the command
+    /// again, if we're going to.
+    pub fn draw(&self, next_run: Option<Duration>) -> Result<()> {
+        let mut out = String::from(CLEAR);
+        out.push_str(&self.render(next_run));
+        write_stdout(&out)
+    }
+
+    /// Switch back to the normal screen, and print our final output there.
+    pub fn finish(mut self) -> Result<()> {
+        self.active = false;
+        let mut out = String::from(LEAVE_ALTERNATE_SCREEN);
+        out.push_str(&self.render(None));
+        write_stdout(&out)
+    }
+
+    /// Our header and as much output as will fit on the screen.
+    fn render(&self, next_run: Option<Duration>) -> String {
+        let (rows, cols) = terminal_size();
+        let mut out = String::new();
+
+        // Our header.
+        // Don't round the interval, because it might be less than a second.
+        let interval = humantime::format_duration(self.interval);
+        let title = format!("Every {}: {}", interval, self.cmd);
+        out.push_str(&truncate(&title, cols).bold().to_string());
+        out.push('\n');
+        let elapsed = format_short_duration(self.start.elapsed().unwrap_or_default());
+        let mut status = format!("Run {}, {} elapsed", self.runs, elapsed);
+        if let Some(timeout) = self.matcher.timeout() {
+            status.push_str(&format!(" of {}", format_short_duration(timeout)));
+        }
+        match next_run {
+            Some(next_run) => status.push_str(&format!(
+                ", next run in {}",
+                format_short_duration(next_run)
+            )),
+            None => status.push_str(", finished"),
+        }
+        out.push_str(&truncate(&status, cols));
+        out.push('\n');
+        out.push_str(&truncate(&self.matcher.describe(), cols));
+        out.push_str("\n\n");
+        if let Some(error) = &self.error {
+            out.push_str(&truncate(error, cols).red().bold().to_string());
+            out.push('\n');
+        }
+
+        // As much of the output as will fit, keeping the end, because that's
+        // usually the most interesting part.
+        let available = rows.saturating_sub(HEADER_LINES + 1);
+        let first = self.lines.len().saturating_sub(available);
+        for (i, line) in self.lines.iter().enumerate().skip(first) {
+            let text = truncate(&line.text, cols);
+            let highlighted = match self.matcher.check_line(line) {
+                Some(m) if m.outcome == Outcome::Success => text.green().bold(),
+                Some(_) => text.red().bold(),
+                // Highlight lines which changed since the last run, but not
+                // on the first run, when everything is new.
+                None if self.runs > 1 && self.added[i] => text.reversed(),
+                None => text.normal(),
+            };
+            out.push_str(&highlighted.to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl<'a> Drop for Screen<'a> {
+    fn drop(&mut self) {
+        // If we're bailing out because of an error, at least give the user
+        // their terminal back.
+        if self.active {
+            let _ = write_stdout(LEAVE_ALTERNATE_SCREEN);
+        }
+    }
+}
+
+/// Is standard output a terminal that we can draw on?
+#[cfg(unix)]
+pub fn stdout_is_terminal() -> bool {
+    // SAFETY: `isatty` has no memory-safety requirements.
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+/// We can't tell on this platform, so assume that it is.
+#[cfg(not(unix))]
+pub fn stdout_is_terminal() -> bool {
+    true
+}
+
+/// Write `out` to standard output immediately.
+fn write_stdout(out: &str) -> Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout
+        .write_all(out.as_bytes())
+        .and_then(|()| stdout.flush())
+        .map_err(|source| Error::CouldNotWriteToStdio {
+            dest: "stdout",
+            source,
+        })
+}
+
+/// Cut `text` off at `cols` characters, so it fits on one line.
+fn truncate(text: &str, cols: usize) -> String {
+    text.chars().take(cols).collect()
+}
+
+/// The size of the terminal, in rows and columns.
+#[cfg(unix)]
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: `size` is valid for the duration of the call.
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0
+            && size.ws_row > 0
+            && size.ws_col > 0
+        {
+            return (size.ws_row as usize, size.ws_col as usize);
+        }
+    }
+    default_terminal_size()
+}
+
+#[cfg(not(unix))]
+fn terminal_size() -> (usize, usize) {
+    default_terminal_size()
+}
+
+/// Guess the terminal size from `$LINES` and `$COLUMNS`, or assume 80x24.
+fn default_terminal_size() -> (usize, usize) {
+    let var = |name: &str, default| {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    (var("LINES", 24), var("COLUMNS", 80))
+}
+
+#[test]
+fn long_lines_are_truncated() {
+    assert_eq!(truncate("héllo", 2), "hé");
+    assert_eq!(truncate("hi", 80), "hi");
+}
@@ -1,6 +1,8 @@
 //! Utility functions.
 
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::errors::*;
 
@@ -11,3 +13,75 @@ pub fn env_var(name: &str) -> Result<String> {
         source,
     })
 }
+
+/// Where should we cache data between runs?
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_dir = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_dir.join("alert"))
+}
+
+/// Format a duration for humans, rounded to the nearest second.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = (duration.as_millis() + 500) / 1000;
+    humantime::format_duration(Duration::from_secs(secs as u64)).to_string()
+}
+
+/// Format a duration like `format_duration`, but keep the milliseconds of
+/// anything shorter than a second, so that short intervals aren't shown as
+/// "0s".
+pub fn format_short_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        let millis = Duration::from_millis(duration.as_millis() as u64);
+        humantime::format_duration(millis).to_string()
+    } else {
+        format_duration(duration)
+    }
+}
+
+/// Parse a duration specified by the user. Bare numbers are treated as
+/// seconds, and anything else is parsed using `humantime`, so "90", "90s" and
+/// "1m 30s" all mean the same thing.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    humantime::parse_duration(s).map_err(|_| Error::InvalidDuration {
+        value: s.to_owned(),
+    })
+}
+
+/// Get the name of the current host, if we can.
+pub fn hostname() -> Option<String> {
+    hostname::get()
+        .ok()
+        .map(|h| h.to_string_lossy().into_owned())
+}
+
+#[test]
+fn durations_are_rounded_to_seconds() {
+    assert_eq!(
+        format_duration(Duration::from_millis(3_723_600)),
+        "1h 2m 4s"
+    );
+    assert_eq!(format_duration(Duration::from_millis(100)), "0s");
+}
+
+#[test]
+fn short_durations_keep_milliseconds() {
+    assert_eq!(
+        format_short_duration(Duration::from_micros(500_400)),
+        "500ms"
+    );
+    assert_eq!(format_short_duration(Duration::from_millis(1_600)), "2s");
+}
+
+#[test]
+fn durations_are_parsed() {
+    assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+    assert_eq!(parse_duration("1m 30s").unwrap(), Duration::from_secs(90));
+    assert!(parse_duration("soon").is_err());
+}
@@ -1,5 +1,7 @@
 use cli_test_dir::*;
 use std::env;
+use std::io::{BufRead, BufReader, Read};
+use std::process;
 
 #[test]
 fn no_subcommand_shows_help() {
@@ -107,5 +109,824 @@ fn subcommand_watch_times_out() {
     assert!(output.stderr_str().contains("Command timed out: true"));
 }
 
+#[test]
+fn subcommand_watch_follows_output() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_follows_output");
+    // This command would take a minute to exit on its own.
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--follow", "-s", "ready", "sh", "-c"])
+        .arg("echo ready; sleep 60")
+        .output()
+        .expect_success();
+    assert!(output.stdout_str().contains("ready"));
+    assert!(output.stderr_str().contains("Command succeeded"));
+}
+
+#[test]
+fn subcommand_watch_can_leave_commands_running() {
+    use std::time::{Duration, Instant};
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_can_leave_commands_running");
+    // Like `tail -f`, this keeps running, and we shouldn't wait for it.
+    let start = Instant::now();
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--follow", "--leave-running", "-s", "ready"])
+        .args(&["sh", "-c", "echo ready; sleep 20"])
+        .expect_success();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(output.stdout_str().contains("ready"));
+    assert!(output.stderr_str().contains("Command succeeded"));
+}
+
+#[test]
+fn subcommand_watch_follow_fails_if_nothing_matched() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir =
+        TestDir::new("alert", "subcommand_watch_follow_fails_if_nothing_matched");
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--follow", "-s", "ready", "echo", "not yet"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output
+        .stderr_str()
+        .contains("Command exited before anything matched"));
+    testdir
+        .cmd()
+        .args(&["watch", "--follow", "--until-success", "-s", "ready"])
+        .args(&["echo", "not yet"])
+        .expect_success();
+}
+
+#[test]
+fn subcommand_watch_gives_commands_empty_input() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_gives_commands_empty_input");
+    let output = testdir
+        .cmd()
+        .args(&[
+            "watch",
+            "-s",
+            "^got",
+            "sh",
+            "-c",
+            r#"read line; echo "got $line""#,
+        ])
+        .output_with_stdin("typed\n")
+        .expect_success();
+    assert!(output.stdout_str().contains("got"));
+    assert!(!output.stdout_str().contains("typed"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_watch_can_redraw_the_screen() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_can_redraw_the_screen");
+    let mut cmd = testdir.cmd();
+    cmd.args(&["watch", "--screen", "-s", "good", "echo", "good"]);
+    let (status, output) = run_on_terminal(cmd, b"");
+    assert!(status.success(), "{}", output);
+    // We draw on the alternate screen, and then switch back and show the
+    // final output before notifying.
+    let enter = output.find("\x1b[?1049h").expect("no alternate screen");
+    let leave = output
+        .find("\x1b[?1049l")
+        .expect("alternate screen not left");
+    let notified = output.find("Command succeeded: echo good").unwrap();
+    assert!(enter < leave && leave < notified);
+    assert!(output[leave..].contains("Every 2s: echo good"));
+    assert!(output[leave..].contains("success: /good/"));
+}
+
+#[test]
+fn subcommand_watch_only_redraws_terminals() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_only_redraws_terminals");
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--screen", "-s", "good", "echo", "good"])
+        .output()
+        .expect_success();
+    assert!(!output.stdout_str().contains("\x1b["));
+    assert!(output.stdout_str().contains("good"));
+    assert!(output.stderr_str().contains("Command succeeded: echo good"));
+}
+
+#[test]
+fn subcommand_watch_records_matching_stream() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_records_matching_stream");
+    testdir.create_file(
+        "config.toml",
+        r#"
+[templates.failure]
+message = "matched on {stream}"
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["watch", "-f", "oops", "sh", "-c"])
+        .arg("echo starting; echo oops >&2")
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stdout_str().contains("starting"));
+    assert!(output.stderr_str().contains("oops"));
+    assert!(output.stderr_str().contains("matched on stderr"));
+}
+
+#[test]
+fn subcommand_watch_uses_named_rules() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_uses_named_rules");
+    testdir.create_file(
+        "config.toml",
+        r#"
+[[watch.rules]]
+name = "jobs"
+outcome = "failure"
+pattern = 'job (?P<job>\w+) FAILED: (?P<reason>.*)'
+stream = "stderr"
+message = "{job} failed because {reason}"
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["watch", "--rule", "ready=^ready$"])
+        .args(&["--rule-outcome", "ready=success", "sh", "-c"])
+        .arg("echo 'job a FAILED: ignored'; echo 'job b FAILED: disk full' >&2; echo ready")
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output
+        .stderr_str()
+        .contains("Command failed: b failed because disk full"));
+}
+
+#[test]
+fn subcommand_watch_checks_exit_codes() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_checks_exit_codes");
+    // Fail the first time, and succeed the second time.
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--until-success", "-n", "0", "sh", "-c"])
+        .arg("test -e marker || { touch marker; exit 1; }")
+        .output()
+        .expect_success();
+    assert!(output.stderr_str().contains("Command succeeded"));
+
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--fail-on-exit", "1-5", "sh", "-c", "exit 3"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Command failed"));
+}
+
+#[test]
+fn subcommand_watch_checks_json_output() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_checks_json_output");
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--failure-json", ".status.phase == \"Failed\""])
+        .args(&["--success-json", ".status.phase == \"Succeeded\""])
+        .args(&["echo", r#"{"status": {"phase": "Succeeded"}}"#])
+        .output()
+        .expect_success();
+    assert!(output.stderr_str().contains("Command succeeded"));
+}
+
+#[test]
+fn subcommand_watch_reports_changes() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_reports_changes");
+    // The queue length changes on the third run, but the time always does.
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--on-change", "--ignore", "^time .*", "-n", "0"])
+        .args(&["sh", "-c"])
+        .arg("n=$(cat n 2>/dev/null || echo 0); echo \"time $$\"; echo \"queue $((n / 2))\"; echo $((n + 1)) > n")
+        .output()
+        .expect_success();
+    assert!(output.stderr_str().contains("Command output changed"));
+    assert!(output.stderr_str().contains("-queue 0\n+queue 1"));
+}
+
+#[test]
+fn subcommand_watch_checks_extracted_numbers() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_checks_extracted_numbers");
+    // The queue drains by one job per run, crossing 2 on the way down.
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--extract", r"pending: (\d+)", "--success-when", "<= 0"])
+        .args(&["--notify-crossing", "2", "-n", "0", "sh", "-c"])
+        .arg("n=$(cat n 2>/dev/null || echo 3); echo \"pending: $n\"; echo $((n - 1)) > n")
+        .output()
+        .expect_success();
+    assert!(output.stderr_str().contains("Command crossed threshold"));
+    assert!(output.stderr_str().contains("Fell to 1 (threshold 2)"));
+    assert!(output.stderr_str().contains("Command succeeded"));
+}
+
+#[test]
+fn subcommand_watch_stops_after_max_runs() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_stops_after_max_runs");
+    let output = testdir
+        .cmd()
+        .args(&["watch", "--success", "^done$", "--max-runs", "3"])
+        .args(&["--interval", "50ms", "--backoff", "exponential", "--jitter"])
+        .args(&["sh", "-c", "echo run >> runs"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Command timed out"));
+    assert!(output.stderr_str().contains("Stopped after 3 runs"));
+    testdir.expect_contains("runs", "run\nrun\nrun\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_watch_reports_interruptions() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_reports_interruptions");
+    // Stop `alert` while it's waiting between runs.
+    let start = Instant::now();
+    let child = testdir
+        .cmd()
+        .args(&["watch", "--success", "^done$", "--interval", "60s"])
+        .args(&["sh", "-c", "echo running"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run command");
+    thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let output = child.wait_with_output().expect("could not run command");
+    assert!(start.elapsed() < Duration::from_secs(20));
+    assert_eq!(output.status.signal(), Some(libc::SIGTERM));
+    assert!(output.stderr_str().contains("Command interrupted: sh -c"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_watch_follow_reports_interruptions() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir =
+        TestDir::new("alert", "subcommand_watch_follow_reports_interruptions");
+    // Stop `alert` while it's following a command which never finishes.
+    let start = Instant::now();
+    let child = testdir
+        .cmd()
+        .args(&["watch", "--follow", "--success", "^done$"])
+        .args(&["sh", "-c", "echo running; sleep 60"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run command");
+    thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let output = child.wait_with_output().expect("could not run command");
+    assert!(start.elapsed() < Duration::from_secs(20));
+    assert_eq!(output.status.signal(), Some(libc::SIGTERM));
+    assert!(output.stderr_str().contains("Command interrupted: sh -c"));
+    assert!(output.stderr_str().contains("Interrupted by SIGTERM"));
+}
+
+#[test]
+fn subcommand_watch_file_matches_log_lines() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_file_matches_log_lines");
+    testdir.create_file("build.log", "compiling\nerror: oops\n");
+    let output = testdir
+        .cmd()
+        .args(&["watch-file", "--from-start", "-f", "^error", "-t", "5"])
+        .arg("build.log")
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stdout_str().contains("error: oops"));
+    assert!(output.stderr_str().contains("Job failed: build.log"));
+}
+
+#[test]
+fn subcommand_watch_file_reports_read_errors() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_file_reports_read_errors");
+    testdir.create_file("logs/placeholder", "");
+    let output = testdir
+        .cmd()
+        .args(&["watch-file", "--from-start", "-f", "^error", "logs"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Could not read logs ("));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_watch_file_reports_interruptions() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_watch_file_reports_interruptions");
+    testdir.create_file("build.log", "compiling\n");
+    let start = Instant::now();
+    let child = testdir
+        .cmd()
+        .args(&["watch-file", "-f", "^error", "build.log"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run command");
+    thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let output = child.wait_with_output().expect("could not run command");
+    assert!(start.elapsed() < Duration::from_secs(20));
+    assert_eq!(output.status.signal(), Some(libc::SIGINT));
+    assert!(output.stderr_str().contains("Job interrupted: build.log"));
+    assert!(output.stderr_str().contains("Interrupted by SIGINT"));
+}
+
+#[test]
+fn templates_customize_notifications() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "templates_customize_notifications");
+    testdir.create_file(
+        "config.toml",
+        r#"
+[templates.failure]
+title = "{team} job failed"
+message = "{command} exited with {exit_code}"
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["run", "--field", "team=data", "sh", "-c", "exit 3"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output
+        .stderr_str()
+        .contains("data job failed: sh -c 'exit 3' exited with 3"));
+}
+
+#[test]
+fn priority_can_be_overridden() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "priority_can_be_overridden");
+    testdir.create_file(
+        "config.toml",
+        r#"
+[templates.success]
+title = "Done ({priority})"
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["run", "--priority", "low", "true"])
+        .output()
+        .expect_success();
+    assert!(output.stderr_str().contains("Done (low): true"));
+}
+
+#[test]
+fn misconfigured_notifiers_are_reported_before_running() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new(
+        "alert",
+        "misconfigured_notifiers_are_reported_before_running",
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_NOTIFIER", "notifyapp")
+        .env("NOTIFYAPP_KEY", " ")
+        .args(&["run", "touch", "ran"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("key is empty"));
+    testdir.expect_no_such_path("ran");
+}
+
+#[test]
+fn notifiers_only_send_configured_outcomes() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "notifiers_only_send_configured_outcomes");
+    testdir.create_file(
+        "config.toml",
+        r#"
+[notifiers.console]
+outcomes = ["failure"]
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["run", "true"])
+        .expect_success();
+    assert!(!output.stderr_str().contains("Command succeeded"));
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["run", "false"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Command failed: false"));
+}
+
+#[test]
+fn subcommand_run_times_out() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_run_times_out");
+    let output = testdir
+        .cmd()
+        .args(&[
+            "run",
+            "--timeout",
+            "1s",
+            "--kill-after",
+            "1s",
+            "sleep",
+            "30",
+        ])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Command timed out: sleep 30"));
+    assert!(output.stderr_str().contains("Gave up after 1s"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_run_lets_timed_commands_use_the_terminal() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new(
+        "alert",
+        "subcommand_run_lets_timed_commands_use_the_terminal",
+    );
+
+    // `--timeout` puts the command in its own process group, which needs to
+    // be in the foreground to read from the terminal.
+    let mut cmd = testdir.cmd();
+    cmd.args(&["run", "--timeout", "5s", "--kill-after", "1s", "sh", "-c"])
+        .arg("read line; echo \"got $line\"");
+    let (status, output) = run_on_terminal(cmd, b"hello\n");
+    assert!(status.success(), "{}", output);
+    assert!(output.contains("got hello"));
+    assert!(output.contains("Command succeeded"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_run_preserves_signal_exit_status() {
+    use std::os::unix::process::ExitStatusExt;
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_run_preserves_signal_exit_status");
+    let output = testdir
+        .cmd()
+        .args(&["run", "sh", "-c", "kill -TERM $$"])
+        .output()
+        .expect("could not run command");
+    assert_eq!(output.status.signal(), Some(libc::SIGTERM));
+    assert!(output.stderr_str().contains("Interrupted by SIGTERM"));
+}
+
+#[test]
+fn subcommand_run_reports_spawn_errors() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_run_reports_spawn_errors");
+    let output = testdir
+        .cmd()
+        .args(&["run", "no-such-command-for-alert"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output
+        .stderr_str()
+        .contains("Could not run command: no-such-command-for-alert"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_run_reports_interruptions() {
+    use std::os::unix::process::ExitStatusExt;
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_run_reports_interruptions");
+    let output = testdir
+        .cmd()
+        .args(&["run", "sh", "-c", "kill -INT $$"])
+        .output()
+        .expect("could not run command");
+    assert_eq!(output.status.signal(), Some(libc::SIGINT));
+    assert!(output.stderr_str().contains("Command interrupted: sh -c"));
+}
+
+#[test]
+#[cfg(unix)]
+fn subcommand_run_forwards_sigint_to_the_command() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir =
+        TestDir::new("alert", "subcommand_run_forwards_sigint_to_the_command");
+    // Send SIGINT to `alert` alone, the way a CI runner might.
+    let start = Instant::now();
+    let child = testdir
+        .cmd()
+        .args(&["run", "sh", "-c", "sleep 30; echo not interrupted"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run command");
+    thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let output = child.wait_with_output().expect("could not run command");
+    assert!(start.elapsed() < Duration::from_secs(20));
+    assert_eq!(output.status.signal(), Some(libc::SIGINT));
+    assert!(!output.stdout_str().contains("not interrupted"));
+    assert!(output.stderr_str().contains("Command interrupted: sh -c"));
+}
+
+#[test]
+fn min_duration_suppresses_quick_successes() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "min_duration_suppresses_quick_successes");
+    let output = testdir
+        .cmd()
+        .args(&["run", "--min-duration", "1m", "true"])
+        .output()
+        .expect_success();
+    assert!(!output.stderr_str().contains("Command succeeded"));
+
+    let output = testdir
+        .cmd()
+        .args(&["run", "--min-duration", "1m", "false"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("Command failed: false"));
+}
+
+#[test]
+fn invalid_min_duration_is_reported_when_loading_config() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new(
+        "alert",
+        "invalid_min_duration_is_reported_when_loading_config",
+    );
+    testdir.create_file(
+        "config.toml",
+        r#"
+[run]
+min_duration = "soon"
+"#,
+    );
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["run", "touch", "ran"])
+        .output()
+        .expect("could not run command");
+    assert!(!output.status.success());
+    assert!(output.stderr_str().contains("soon"));
+    testdir.expect_no_such_path("ran");
+}
+
+#[test]
+fn shell_init_prints_hooks() {
+    let testdir = TestDir::new("alert", "shell_init_prints_hooks");
+    for &shell in &["bash", "zsh", "fish"] {
+        let output = testdir
+            .cmd()
+            .args(&["shell-init", "--min-duration", "2m", shell])
+            .output()
+            .expect_success();
+        assert!(output.stdout_str().contains("alert shell-hook"));
+        assert!(output.stdout_str().contains("120"));
+    }
+}
+
+#[test]
+fn shell_init_ignores_broken_config() {
+    let testdir = TestDir::new("alert", "shell_init_ignores_broken_config");
+    testdir.create_file("config.toml", "[run\n");
+    let output = testdir
+        .cmd()
+        .env("ALERT_CONFIG", "config.toml")
+        .args(&["shell-init", "bash"])
+        .output()
+        .expect_success();
+    assert!(output.stdout_str().contains("alert shell-hook"));
+    assert!(output.stdout_str().contains("-ge 30"));
+}
+
+#[test]
+fn shell_hook_reports_commands() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "shell_hook_reports_commands");
+    let output = testdir
+        .cmd()
+        .env_remove("WINDOWID")
+        .args(&["shell-hook", "--exit-code", "2", "--duration", "90"])
+        .arg("--")
+        .arg("make -j8 && make test")
+        .output()
+        .expect_success();
+    assert!(output
+        .stderr_str()
+        .contains("Command failed: make -j8 && make test"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn subcommand_wait_reports_process_exit() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_wait_reports_process_exit");
+    // `cat` runs until we close its input.
+    let mut child = process::Command::new("cat")
+        .stdin(process::Stdio::piped())
+        .spawn()
+        .expect("could not run cat");
+    let mut waiter = testdir
+        .cmd()
+        .env("RUST_LOG", "alert=debug")
+        .args(&["wait", "--pid", &child.id().to_string()])
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .expect("could not run alert");
+
+    // Only let `cat` exit once `alert` has found it and started waiting.
+    let mut stderr = BufReader::new(waiter.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("Waiting for process") {
+        line.clear();
+        assert!(
+            stderr.read_line(&mut line).unwrap() > 0,
+            "alert exited early"
+        );
+    }
+    drop(child.stdin.take());
+
+    let mut rest = String::new();
+    stderr.read_to_string(&mut rest).unwrap();
+    assert!(waiter.wait().unwrap().success());
+    // Don't reap `cat` until `alert` is done with it, so its PID stays valid.
+    child.wait().expect("could not wait for cat");
+    // Depending on whether `alert` can trace `cat`, we may not know how it
+    // exited.
+    assert!(
+        rest.contains("Command succeeded: cat")
+            || rest.contains("Command finished: cat")
+    );
+}
+
+#[test]
+fn subcommand_send_reports_custom_notifications() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir =
+        TestDir::new("alert", "subcommand_send_reports_custom_notifications");
+    let output = testdir
+        .cmd()
+        .args(&["send", "--outcome", "failure", "--title", "Backup failed"])
+        .args(&["--message", "-"])
+        .output_with_stdin("disk full\n")
+        .expect_success();
+    assert!(output.stderr_str().contains("Backup failed: disk full"));
+}
+
+#[test]
+fn subcommand_send_uses_default_titles() {
+    env::set_var("ALERT_NOTIFIER", "console");
+    env::set_var("CLICOLOR", "0");
+    let testdir = TestDir::new("alert", "subcommand_send_uses_default_titles");
+    let output = testdir
+        .cmd()
+        .args(&["send", "--message", "all done"])
+        .expect_success();
+    assert!(output.stderr_str().contains("Command succeeded: all done"));
+}
+
 // TODO: File an issue for `expect_failure` against `cli_test_dir`.
 // TODO: User can include an extra message.
+
+/// Run `cmd` in its own session on a pseudo-terminal, the way a shell would,
+/// typing `input` into it. Returns its exit status and everything it wrote.
+#[cfg(unix)]
+fn run_on_terminal(
+    mut cmd: process::Command,
+    input: &[u8],
+) -> (process::ExitStatus, String) {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::ptr;
+
+    let (mut master, slave) = unsafe {
+        let (mut master, mut slave) = (0, 0);
+        let result = libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        );
+        assert_eq!(result, 0, "could not open a pseudo-terminal");
+        (File::from_raw_fd(master), File::from_raw_fd(slave))
+    };
+    cmd.stdin(slave.try_clone().unwrap())
+        .stdout(slave.try_clone().unwrap())
+        .stderr(slave);
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            libc::ioctl(0, libc::TIOCSCTTY as _, 0);
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().expect("could not run command");
+    // Close our copies of the terminal, so we see when the command is done.
+    drop(cmd);
+    master.write_all(input).unwrap();
+    let status = child.wait().unwrap();
+
+    // Once nothing has the terminal open, reading it fails with `EIO`.
+    let mut output = vec![];
+    let _ = master.read_to_end(&mut output);
+    (status, String::from_utf8_lossy(&output).into_owned())
+}